@@ -0,0 +1,242 @@
+//! Custo das callbacks de áudio de TX e de RX, com a verificação de que,
+//! desde a primeira chamada, elas não alocam nem liberam memória no heap.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use crossbeam_channel::{bounded, unbounded, Receiver};
+use modem::call_progress::{CallProgressEvent, Country};
+use modem::kiss;
+use modem::link::{
+    link_handoff, modem_rate, KissFramer, LinkBuilder, LinkParams, RxAudio, TxAudio,
+};
+use modem::v21::Standard;
+use rtrb::{Producer, RingBuffer};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Alocador do sistema que conta as operações feitas pela thread em que a
+/// contagem está ligada.
+struct CountingAllocator;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static OPERATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count() {
+    let _ = COUNTING.try_with(|counting| {
+        if counting.get() {
+            OPERATIONS.with(|operations| operations.set(operations.get() + 1));
+        }
+    });
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count();
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Operações no heap feitas por `f` nesta thread.
+fn heap_operations(f: impl FnOnce()) -> usize {
+    OPERATIONS.with(|operations| operations.set(0));
+    COUNTING.with(|counting| counting.set(true));
+    f();
+    COUNTING.with(|counting| counting.set(false));
+    OPERATIONS.with(|operations| operations.get())
+}
+
+/// Um modem visto pelas suas callbacks de TX e de RX, com as filas do lado
+/// de comandos e a ligação montada como no `main`.
+struct Modem {
+    tx: TxAudio,
+    rx: RxAudio,
+    to_line: Producer<u8>,
+    from_line: Receiver<u8>,
+    frames: Receiver<Vec<u8>>,
+    events: Receiver<CallProgressEvent>,
+}
+
+impl Modem {
+    fn new(params: LinkParams, answer: bool, transport_rate: usize, block: usize) -> Self {
+        let srate = modem_rate(params.standard, transport_rate);
+        let (to_terminal, from_line) = bounded(4096);
+        let (frames_tx, frames) = unbounded();
+        let (call_progress, events) = bounded(16);
+        let carrier = Arc::new(AtomicBool::new(false));
+        let links = LinkBuilder {
+            params,
+            tx_srate: srate,
+            rx_srate: srate,
+            carrier: carrier.clone(),
+            rx_baud_rate: Arc::new(AtomicUsize::new(0)),
+            to_terminal,
+            frames: frames_tx,
+            call_progress,
+        };
+        let (mut tx_link, tx_slot) = link_handoff();
+        let (mut rx_link, rx_slot) = link_handoff();
+        tx_link.set(Some(links.tx(answer, None)));
+        rx_link.set(Some(links.rx(answer)));
+
+        let (to_line, from_terminal) = RingBuffer::new(4096);
+        Self {
+            tx: TxAudio::new(
+                tx_slot,
+                from_terminal,
+                params.standard,
+                transport_rate,
+                block,
+            ),
+            rx: RxAudio::new(rx_slot, carrier, params.standard, transport_rate, block),
+            to_line,
+            from_line,
+            frames,
+            events,
+        }
+    }
+}
+
+/// Modem de origem ligado a um de resposta, como dois pares de
+/// dispositivos de áudio à mesma taxa; o de origem transmite ao outro.
+struct Callbacks {
+    originate: Modem,
+    answer: Modem,
+    /// No Bell 202, os quadros KISS vão pelo HDLC montado fora da callback
+    framer: Option<KissFramer>,
+    kiss_frame: Vec<u8>,
+    originate_out: Vec<f32>,
+    answer_out: Vec<f32>,
+    next_byte: u8,
+    received: usize,
+}
+
+impl Callbacks {
+    fn new(params: LinkParams, transport_rate: usize) -> Self {
+        let framer = (params.standard == Standard::Bell202)
+            .then(|| KissFramer::new(params.standard.baud_rate(false)));
+        // Blocos de 10 ms
+        let block = transport_rate / 100;
+        Self {
+            originate: Modem::new(params, false, transport_rate, block),
+            answer: Modem::new(params, true, transport_rate, block),
+            framer,
+            kiss_frame: kiss::encode(0, &[0x55; 32]),
+            originate_out: vec![0.; block],
+            answer_out: vec![0.; block],
+            next_byte: 0,
+            received: 0,
+        }
+    }
+
+    /// Lado de comandos do modem de origem, que sempre tem o que
+    /// transmitir.
+    fn feed(&mut self) {
+        let to_line = &mut self.originate.to_line;
+        let Some(framer) = self.framer.as_mut() else {
+            if to_line.push(self.next_byte).is_ok() {
+                self.next_byte = self.next_byte.wrapping_add(1);
+            }
+            return;
+        };
+        // Um quadro de 32 bytes não chega a 600 níveis de bit
+        if to_line.slots() >= 1024 {
+            for &b in &self.kiss_frame {
+                framer.put_byte(b, |level| {
+                    let _ = to_line.push(level);
+                });
+            }
+        }
+    }
+
+    /// Uma chamada de cada callback dos dois modems.
+    fn process(&mut self) {
+        self.originate.tx.process(&mut self.originate_out);
+        self.answer.tx.process(&mut self.answer_out);
+        self.answer.rx.process(&self.originate_out);
+        self.originate.rx.process(&self.answer_out);
+    }
+
+    fn drain(&mut self) {
+        self.received += self.answer.from_line.try_iter().count();
+        self.received += self.answer.frames.try_iter().count();
+        self.originate.from_line.try_iter().for_each(drop);
+        self.originate.events.try_iter().for_each(drop);
+    }
+}
+
+/// Confere que as callbacks não tocam no heap nos primeiros 10 s, que
+/// cobrem a sequência de resposta do V.25 e a troca de dados, e que o modem
+/// de resposta recebe o que o de origem transmite.
+fn check_no_allocations(callbacks: &mut Callbacks) {
+    for _ in 0..1000 {
+        callbacks.feed();
+        assert_eq!(heap_operations(|| callbacks.process()), 0);
+        callbacks.drain();
+    }
+    // Os quadros do Bell 202 são montados por outra thread
+    std::thread::sleep(Duration::from_millis(50));
+    callbacks.drain();
+    assert!(callbacks.received > 0);
+}
+
+fn bench_callbacks(c: &mut Criterion) {
+    let params = |standard| LinkParams {
+        standard,
+        ..LinkParams::default()
+    };
+    for (name, params, transport_rate) in [
+        ("callbacks V.21 48000", params(Standard::V21), 48000),
+        (
+            "callbacks V.22 44100 resampled",
+            params(Standard::V22),
+            44100,
+        ),
+        ("callbacks Bell 202 48000", params(Standard::Bell202), 48000),
+        (
+            "callbacks V.21 48000 autobaud",
+            LinkParams {
+                autobaud: true,
+                ..params(Standard::V21)
+            },
+            48000,
+        ),
+        (
+            "callbacks V.21 48000 call progress",
+            LinkParams {
+                country: Some(Country::NorthAmerica),
+                ..params(Standard::V21)
+            },
+            48000,
+        ),
+    ] {
+        let mut callbacks = Callbacks::new(params, transport_rate);
+        check_no_allocations(&mut callbacks);
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                callbacks.feed();
+                callbacks.process();
+                callbacks.drain();
+            })
+        });
+    }
+}
+
+criterion_group!(benches, bench_callbacks);
+criterion_main!(benches);
@@ -0,0 +1,67 @@
+//! Vazão do modulador e do demodulador do V.21, em amostras por segundo,
+//! para estimar quantos canais cabem num núcleo.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use modem::uart::{Framing, UartTx};
+use modem::v21::{CarrierParams, Standard, BAUD_RATE, V21RX, V21TX};
+use rand::distributions::{Distribution, Uniform};
+use rand::SeedableRng;
+
+const SAMPLE_RATES: [usize; 3] = [8000, 44100, 48000];
+
+/// Um segundo de níveis de linha de bytes aleatórios, na taxa dada.
+fn line_levels(srate: usize) -> Vec<u8> {
+    let mut gen = rand_pcg::Pcg32::seed_from_u64(42);
+    let d_byte = Uniform::new(0, 255);
+    let mut uart_tx = UartTx::with_rates(srate, BAUD_RATE, Framing::default());
+    while uart_tx.queued_samples() < srate {
+        uart_tx.put_byte(d_byte.sample(&mut gen));
+    }
+    let mut levels = vec![1; srate];
+    uart_tx.get_samples(&mut levels);
+    levels
+}
+
+fn bench_v21tx(c: &mut Criterion) {
+    let (omega1, omega0) = Standard::V21.omegas(false);
+    let mut group = c.benchmark_group("V21TX");
+    for srate in SAMPLE_RATES {
+        let levels = line_levels(srate);
+        let mut samples = vec![0.; srate];
+        let mut v21_tx = V21TX::new(1. / srate as f32, omega1, omega0);
+        group.throughput(Throughput::Elements(srate as u64));
+        group.bench_function(BenchmarkId::from_parameter(srate), |b| {
+            b.iter(|| v21_tx.modulate(&levels, &mut samples))
+        });
+    }
+    group.finish();
+}
+
+fn bench_v21rx(c: &mut Criterion) {
+    let (omega1, omega0) = Standard::V21.omegas(false);
+    let mut group = c.benchmark_group("V21RX");
+    for srate in SAMPLE_RATES {
+        let speriod = 1. / srate as f32;
+        let mut samples = vec![0.; srate];
+        V21TX::new(speriod, omega1, omega0).modulate(&line_levels(srate), &mut samples);
+        let mut levels = vec![1; srate];
+        // A 8 kHz o símbolo não tem um número inteiro de amostras; o
+        // demodulador usa o arredondado, o que não muda o seu custo
+        let samples_per_symbol = (srate as f32 / BAUD_RATE as f32).round() as usize;
+        let mut v21_rx = V21RX::new(
+            speriod,
+            samples_per_symbol,
+            omega1,
+            omega0,
+            &CarrierParams::default(),
+        );
+        group.throughput(Throughput::Elements(srate as u64));
+        group.bench_function(BenchmarkId::from_parameter(srate), |b| {
+            b.iter(|| v21_rx.demodulate(&samples, &mut levels))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_v21tx, bench_v21rx);
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+use std::io;
+
+/// Origem das amostras de RX de um transporte de áudio lido em blocos
+/// (arquivo WAV, PCM cru etc.), em vez de entregues por callback como nos
+/// dispositivos de áudio.
+pub trait AudioSource: Send {
+    fn sample_rate(&self) -> usize;
+
+    /// Preenche o início de `samples` com as próximas amostras, entre -1 e 1,
+    /// e devolve quantas foram lidas; menos que o pedido indica o fim do
+    /// fluxo.
+    fn read(&mut self, samples: &mut [f32]) -> io::Result<usize>;
+}
+
+/// Destino das amostras de TX de um transporte de áudio gravado em blocos.
+pub trait AudioSink: Send {
+    fn sample_rate(&self) -> usize;
+
+    /// Grava as amostras, limitando-as ao intervalo de -1 a 1.
+    fn write(&mut self, samples: &[f32]) -> io::Result<()>;
+
+    /// Entrega o que estiver em buffer, de modo que o destino fique
+    /// utilizável mesmo que o programa seja interrompido.
+    fn flush(&mut self) -> io::Result<()>;
+
+    /// Termina o fluxo.
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
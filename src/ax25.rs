@@ -0,0 +1,181 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Valor de PID para quadros sem protocolo de camada 3 (ex.: APRS).
+pub const PID_NO_LAYER3: u8 = 0xf0;
+
+/// Controle de um quadro UI (unnumbered information) com P/F zerado.
+pub const CONTROL_UI: u8 = 0x03;
+
+const MAX_DIGIPEATERS: usize = 8;
+
+/// Indicativo com SSID, como aparece nos campos de endereço do AX.25.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Address {
+    pub callsign: String,
+    pub ssid: u8,
+    /// Bit C nos endereços de destino e origem, bit H (já repetido) nos
+    /// digipeaters.
+    pub flag: bool,
+}
+
+impl Address {
+    fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let callsign: String = bytes[..6]
+            .iter()
+            .map(|&b| (b >> 1) as char)
+            .collect::<String>()
+            .trim_end()
+            .to_string();
+        if callsign.is_empty() || !callsign.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format!("invalid callsign {:?}", callsign));
+        }
+        Ok(Self {
+            callsign,
+            ssid: (bytes[6] >> 1) & 0x0f,
+            flag: bytes[6] & 0x80 != 0,
+        })
+    }
+
+    fn encode(&self, last: bool, out: &mut Vec<u8>) {
+        let padded = format!("{:<6}", self.callsign);
+        out.extend(padded.bytes().take(6).map(|b| b << 1));
+        out.push(0x60 | ((self.flag as u8) << 7) | (self.ssid << 1) | last as u8);
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.callsign)?;
+        if self.ssid != 0 {
+            write!(f, "-{}", self.ssid)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (callsign, ssid) = match s.split_once('-') {
+            Some((callsign, ssid)) => (
+                callsign,
+                ssid.parse::<u8>()
+                    .ok()
+                    .filter(|&ssid| ssid <= 15)
+                    .ok_or_else(|| format!("invalid SSID in '{}'", s))?,
+            ),
+            None => (s, 0),
+        };
+        if callsign.is_empty()
+            || callsign.len() > 6
+            || !callsign.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return Err(format!("invalid callsign '{}'", s));
+        }
+        Ok(Self {
+            callsign: callsign.to_ascii_uppercase(),
+            ssid,
+            flag: false,
+        })
+    }
+}
+
+/// Quadro AX.25 como entregue pelo HDLC, sem flags nem FCS.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub destination: Address,
+    pub source: Address,
+    pub digipeaters: Vec<Address>,
+    pub control: u8,
+    /// Presente apenas em quadros I e UI.
+    pub pid: Option<u8>,
+    pub info: Vec<u8>,
+}
+
+impl Frame {
+    /// Quadro UI sem camada 3, o formato usado pelo APRS.
+    pub fn ui(destination: Address, source: Address, info: &[u8]) -> Self {
+        Self {
+            destination,
+            source,
+            digipeaters: Vec::new(),
+            control: CONTROL_UI,
+            pid: Some(PID_NO_LAYER3),
+            info: info.to_vec(),
+        }
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let mut addresses = Vec::new();
+        let mut rest = bytes;
+        loop {
+            if rest.len() < 7 {
+                return Err("truncated address field".to_string());
+            }
+            let (field, tail) = rest.split_at(7);
+            addresses.push(Address::parse(field)?);
+            rest = tail;
+            if field[6] & 1 != 0 {
+                break;
+            }
+            if addresses.len() == 2 + MAX_DIGIPEATERS {
+                return Err("too many digipeaters".to_string());
+            }
+        }
+        if addresses.len() < 2 {
+            return Err("missing source address".to_string());
+        }
+
+        let (&control, rest) = rest
+            .split_first()
+            .ok_or_else(|| "missing control field".to_string())?;
+        let has_pid = control & 1 == 0 || control & 0xef == CONTROL_UI;
+        let (pid, info) = if has_pid {
+            let (&pid, info) = rest
+                .split_first()
+                .ok_or_else(|| "missing PID field".to_string())?;
+            (Some(pid), info)
+        } else {
+            (None, rest)
+        };
+
+        let mut addresses = addresses.into_iter();
+        Ok(Self {
+            destination: addresses.next().unwrap(),
+            source: addresses.next().unwrap(),
+            digipeaters: addresses.collect(),
+            control,
+            pid,
+            info: info.to_vec(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(16 + 7 * self.digipeaters.len() + self.info.len());
+        self.destination.encode(false, &mut out);
+        self.source.encode(self.digipeaters.is_empty(), &mut out);
+        for (i, digipeater) in self.digipeaters.iter().enumerate() {
+            digipeater.encode(i + 1 == self.digipeaters.len(), &mut out);
+        }
+        out.push(self.control);
+        out.extend(self.pid);
+        out.extend_from_slice(&self.info);
+        out
+    }
+}
+
+/// Formato de monitor dos TNCs (TNC2): `ORIGEM>DESTINO,DIGI*:info`.
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}>{}", self.source, self.destination)?;
+        for digipeater in &self.digipeaters {
+            write!(f, ",{}", digipeater)?;
+            if digipeater.flag {
+                write!(f, "*")?;
+            }
+        }
+        write!(f, ":{}", String::from_utf8_lossy(&self.info))
+    }
+}
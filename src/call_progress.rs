@@ -0,0 +1,270 @@
+use crate::dtmf::Goertzel;
+use crossbeam_channel::Sender;
+use std::str::FromStr;
+
+/// Frequências dos três segmentos dos tons de informação especial (SIT),
+/// em Hz, na ordem em que soam.
+pub const SIT_FREQUENCIES: [f32; 3] = [913.8, 1370.6, 1776.7];
+
+/// Duração, em segundos, dos blocos analisados; a resolução de 40 Hz separa
+/// os tons norte-americanos de 440 e 480 Hz.
+const BLOCK: f32 = 0.025;
+
+/// Fração mínima da energia do bloco que precisa estar nos tons procurados.
+const RATIO: f32 = 0.5;
+
+/// Nível mínimo, em dB relativos à escala cheia, do tom mais forte.
+const MIN_LEVEL: f32 = -45.;
+
+/// Tolerância relativa das durações das cadências.
+const CADENCE_TOLERANCE: f32 = 0.2;
+
+/// Duração, em segundos, que um tom contínuo precisa ter para ser tomado
+/// como tom de discar.
+const DIAL_TONE_TIME: f32 = 1.;
+
+/// Ciclos seguidos com a cadência de ocupado para dar a linha como ocupada.
+const BUSY_CYCLES: usize = 2;
+
+/// Blocos sem tom de informação especial tolerados entre seus segmentos.
+const SIT_MAX_GAP_BLOCKS: usize = 2;
+
+/// País cujo plano de tons de progressão de chamada é usado.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Country {
+    NorthAmerica,
+    Brazil,
+}
+
+impl FromStr for Country {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "na" | "us" => Ok(Country::NorthAmerica),
+            "br" => Ok(Country::Brazil),
+            _ => Err(format!("invalid country '{}' (expected na or br)", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Country {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Country::NorthAmerica => write!(f, "na"),
+            Country::Brazil => write!(f, "br"),
+        }
+    }
+}
+
+/// Plano de tons de progressão de chamada. Durações em segundos.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TonePlan {
+    /// Frequências dos tons de discar, de ocupado e de chamada, em Hz
+    pub frequencies: &'static [f32],
+    /// Cadências (ligado, desligado) dos tons de ocupado e de
+    /// congestionamento
+    pub busy: &'static [(f32, f32)],
+    /// Tempo ligado do tom de chamada
+    pub ringback_on: f32,
+}
+
+impl Country {
+    pub fn tone_plan(self) -> TonePlan {
+        match self {
+            // Tom de discar 350 + 440 Hz, ocupado e congestionamento
+            // (reorder) 480 + 620 Hz e chamada 440 + 480 Hz, 2 s a cada 6 s
+            Country::NorthAmerica => TonePlan {
+                frequencies: &[350., 440., 480., 620.],
+                busy: &[(0.5, 0.5), (0.25, 0.25)],
+                ringback_on: 2.,
+            },
+            // Todos os tons em 425 Hz; chamada 1 s a cada 5 s
+            Country::Brazil => TonePlan {
+                frequencies: &[425.],
+                busy: &[(0.25, 0.25)],
+                ringback_on: 1.,
+            },
+        }
+    }
+}
+
+/// Tons de progressão de chamada reconhecidos.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallProgressEvent {
+    /// Tom contínuo, antes da discagem
+    DialTone,
+    /// Um toque do tom de chamada terminou
+    Ringback,
+    /// Cadência de ocupado ou de congestionamento
+    Busy,
+    /// Tons de informação especial (número inexistente, por exemplo)
+    SpecialInformation,
+}
+
+/// Analisador de progressão de chamada. Filtros de Goertzel medem, em
+/// blocos de 25 ms, a energia nas frequências do plano de tons do país e
+/// nas dos tons de informação especial; as durações dos trechos com e sem
+/// tom são comparadas às cadências do plano.
+///
+/// Como o tom de discar só se distingue por ser contínuo, o evento
+/// `DialTone` também aparece durante toques longos; só faz sentido esperá-lo
+/// antes de discar.
+pub struct CallProgress {
+    plan: TonePlan,
+    block_samples: usize,
+    block_duration: f32,
+    tones: Vec<Goertzel>,
+    sit_tones: Vec<Goertzel>,
+    events: Sender<CallProgressEvent>,
+
+    // Atributos auxiliares
+    tone_amplitudes: Vec<f32>,
+    sit_amplitudes: [f32; SIT_FREQUENCIES.len()],
+    energy: f32,
+    count: usize,
+    tone_on: bool,
+    run_blocks: usize,
+    last_on: Option<f32>,
+    busy_cycles: usize,
+    sit_next: usize,
+    sit_gap: usize,
+}
+
+impl CallProgress {
+    pub fn new(sampling_period: f32, country: Country, events: Sender<CallProgressEvent>) -> Self {
+        let plan = country.tone_plan();
+        let block_samples = (BLOCK / sampling_period).round() as usize;
+        Self {
+            plan,
+            block_samples,
+            block_duration: block_samples as f32 * sampling_period,
+            tones: plan
+                .frequencies
+                .iter()
+                .map(|&f| Goertzel::new(f, sampling_period))
+                .collect(),
+            sit_tones: SIT_FREQUENCIES
+                .iter()
+                .map(|&f| Goertzel::new(f, sampling_period))
+                .collect(),
+            events,
+            tone_amplitudes: vec![0.; plan.frequencies.len()],
+            sit_amplitudes: [0.; SIT_FREQUENCIES.len()],
+            energy: 0.,
+            count: 0,
+            tone_on: false,
+            run_blocks: 0,
+            last_on: None,
+            busy_cycles: 0,
+            sit_next: 0,
+            sit_gap: 0,
+        }
+    }
+
+    pub fn put_samples(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            for goertzel in self.tones.iter_mut().chain(self.sit_tones.iter_mut()) {
+                goertzel.put_sample(sample);
+            }
+            self.energy += sample * sample;
+            self.count += 1;
+            if self.count == self.block_samples {
+                self.end_block();
+            }
+        }
+    }
+
+    fn emit(&self, event: CallProgressEvent) {
+        let _ = self.events.try_send(event);
+    }
+
+    fn end_block(&mut self) {
+        let n = self.block_samples;
+        let power = self.energy / n as f32;
+        // Amplitudes em buffers da criação: o analisador roda na callback
+        // de RX, que não pode alocar
+        for (amplitude, goertzel) in self.tone_amplitudes.iter_mut().zip(&mut self.tones) {
+            *amplitude = goertzel.take_amplitude(n);
+        }
+        for (amplitude, goertzel) in self.sit_amplitudes.iter_mut().zip(&mut self.sit_tones) {
+            *amplitude = goertzel.take_amplitude(n);
+        }
+
+        self.update_cadence(present(&self.tone_amplitudes, power));
+
+        // Os segmentos do SIT precisam soar na ordem, com no máximo pequenas
+        // falhas entre eles
+        let sit = (0..SIT_FREQUENCIES.len()).find(|&i| present(&self.sit_amplitudes[i..=i], power));
+        match sit {
+            Some(i) if i == self.sit_next => {
+                self.sit_next += 1;
+                self.sit_gap = 0;
+                if self.sit_next == SIT_FREQUENCIES.len() {
+                    self.sit_next = 0;
+                    self.emit(CallProgressEvent::SpecialInformation);
+                }
+            }
+            Some(i) if i + 1 == self.sit_next => self.sit_gap = 0,
+            Some(i) => self.sit_next = (i == 0) as usize,
+            None => {
+                self.sit_gap += 1;
+                if self.sit_gap > SIT_MAX_GAP_BLOCKS {
+                    self.sit_next = 0;
+                }
+            }
+        }
+
+        self.energy = 0.;
+        self.count = 0;
+    }
+
+    fn update_cadence(&mut self, tone_on: bool) {
+        let dial_tone_blocks = (DIAL_TONE_TIME / self.block_duration).ceil() as usize;
+        if tone_on == self.tone_on {
+            self.run_blocks += 1;
+            if tone_on && self.run_blocks == dial_tone_blocks {
+                self.emit(CallProgressEvent::DialTone);
+            }
+            return;
+        }
+
+        let duration = self.run_blocks as f32 * self.block_duration;
+        if self.tone_on {
+            if matches(duration, self.plan.ringback_on) {
+                self.emit(CallProgressEvent::Ringback);
+            }
+            self.last_on = Some(duration);
+        } else {
+            let busy = self.last_on.is_some_and(|on| {
+                self.plan
+                    .busy
+                    .iter()
+                    .any(|&(busy_on, busy_off)| matches(on, busy_on) && matches(duration, busy_off))
+            });
+            if busy {
+                self.busy_cycles += 1;
+                if self.busy_cycles == BUSY_CYCLES {
+                    self.emit(CallProgressEvent::Busy);
+                }
+            } else {
+                self.busy_cycles = 0;
+            }
+        }
+        self.tone_on = tone_on;
+        self.run_blocks = 1;
+    }
+}
+
+/// Indica se os tons de amplitudes dadas estão presentes num bloco com a
+/// potência média dada.
+fn present(amplitudes: &[f32], power: f32) -> bool {
+    let tone_power: f32 = amplitudes.iter().map(|a| a * a / 2.).sum();
+    let strongest = amplitudes.iter().copied().fold(0., f32::max);
+    20. * strongest.log10() >= MIN_LEVEL && tone_power >= RATIO * power
+}
+
+/// Indica se a duração medida corresponde à esperada.
+fn matches(measured: f32, expected: f32) -> bool {
+    (measured - expected).abs() <= CADENCE_TOLERANCE * expected
+}
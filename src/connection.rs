@@ -0,0 +1,101 @@
+use std::time::{Duration, Instant};
+
+/// Estado da conexão, dirigido pelo gancho e pela portadora remota (DCD).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// No gancho
+    Idle,
+    /// Fora do gancho, esperando a portadora remota se firmar
+    Handshaking,
+    /// Portadora remota presente
+    Connected,
+    /// Portadora remota ausente há menos que o atraso de perda
+    CarrierLost,
+    /// Portadora remota perdida; esperando o modem voltar ao gancho
+    HungUp,
+}
+
+/// Transições que devem ser anunciadas ao terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// Conexão estabelecida (`CONNECT`)
+    Connect,
+    /// Conexão perdida (`NO CARRIER`)
+    NoCarrier,
+}
+
+/// Máquina de estados da conexão: acompanha o DCD do demodulador, exige
+/// que a portadora dure um tempo mínimo para dar a conexão como
+/// estabelecida e tolera faltas curtas dela antes de derrubá-la.
+pub struct Connection {
+    state: ConnectionState,
+    detect_time: Duration,
+    loss_delay: Duration,
+
+    // Atributos auxiliares
+    since: Instant,
+}
+
+impl Default for Connection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Connection {
+    pub fn new() -> Self {
+        Self {
+            state: ConnectionState::Idle,
+            detect_time: Duration::ZERO,
+            loss_delay: Duration::ZERO,
+            since: Instant::now(),
+        }
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    /// Sai do gancho e passa a esperar a portadora remota, que precisa
+    /// durar `detect_time`; depois de conectado, faltas menores que
+    /// `loss_delay` são ignoradas.
+    pub fn off_hook(&mut self, now: Instant, detect_time: Duration, loss_delay: Duration) {
+        self.state = ConnectionState::Handshaking;
+        self.detect_time = detect_time;
+        self.loss_delay = loss_delay;
+        self.since = now;
+    }
+
+    pub fn on_hook(&mut self) {
+        self.state = ConnectionState::Idle;
+    }
+
+    /// Atualiza o estado com o DCD visto no instante `now`.
+    pub fn update(&mut self, carrier: bool, now: Instant) -> Option<ConnectionEvent> {
+        let elapsed = now.duration_since(self.since);
+        match (self.state, carrier) {
+            (ConnectionState::Handshaking, false) => {
+                self.since = now;
+                None
+            }
+            (ConnectionState::Handshaking, true) if elapsed >= self.detect_time => {
+                self.state = ConnectionState::Connected;
+                Some(ConnectionEvent::Connect)
+            }
+            (ConnectionState::Connected, false) => {
+                self.state = ConnectionState::CarrierLost;
+                self.since = now;
+                None
+            }
+            (ConnectionState::CarrierLost, true) => {
+                self.state = ConnectionState::Connected;
+                None
+            }
+            (ConnectionState::CarrierLost, false) if elapsed >= self.loss_delay => {
+                self.state = ConnectionState::HungUp;
+                Some(ConnectionEvent::NoCarrier)
+            }
+            _ => None,
+        }
+    }
+}
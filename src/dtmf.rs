@@ -0,0 +1,505 @@
+use crossbeam_channel::Sender;
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+/// Frequências do grupo baixo (linhas do teclado) do Q.23, em Hz.
+pub const LOW_FREQUENCIES: [f32; 4] = [697., 770., 852., 941.];
+
+/// Frequências do grupo alto (colunas do teclado) do Q.23, em Hz.
+pub const HIGH_FREQUENCIES: [f32; 4] = [1209., 1336., 1477., 1633.];
+
+/// Teclas na posição das linhas e colunas de frequências.
+const KEYPAD: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+/// Frequências baixa e alta da tecla dada, em Hz.
+pub fn frequencies(digit: char) -> Option<(f32, f32)> {
+    let digit = digit.to_ascii_uppercase();
+    KEYPAD.iter().enumerate().find_map(|(row, keys)| {
+        let col = keys.iter().position(|&key| key == digit)?;
+        Some((LOW_FREQUENCIES[row], HIGH_FREQUENCIES[col]))
+    })
+}
+
+/// Tecla da linha e da coluna dadas pelos índices em `LOW_FREQUENCIES` e
+/// `HIGH_FREQUENCIES`.
+pub fn digit(row: usize, col: usize) -> char {
+    KEYPAD[row][col]
+}
+
+/// Método de discagem.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DialMode {
+    /// Multifrequencial (DTMF, Q.23)
+    #[default]
+    Tone,
+    /// Decádica, por interrupções do laço
+    Pulse,
+}
+
+impl FromStr for DialMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "tone" => Ok(DialMode::Tone),
+            "pulse" => Ok(DialMode::Pulse),
+            _ => Err(format!(
+                "invalid dial mode '{}' (expected tone or pulse)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for DialMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DialMode::Tone => write!(f, "tone"),
+            DialMode::Pulse => write!(f, "pulse"),
+        }
+    }
+}
+
+/// Parâmetros da discagem. Durações em segundos, níveis em dB relativos à
+/// escala cheia.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DialParams {
+    /// Método usado até o número pedir outro com `T` ou `P`
+    pub mode: DialMode,
+    /// Duração de cada tom DTMF
+    pub tone_duration: f32,
+    /// Silêncio depois de cada tom DTMF
+    pub tone_gap: f32,
+    /// Nível do tom do grupo baixo
+    pub level: f32,
+    /// Nível do grupo alto menos o do grupo baixo (o Q.23 prevê o grupo alto
+    /// um pouco mais forte, para compensar as perdas da linha)
+    pub twist: f32,
+    /// Espera do caractere de pausa (`,`)
+    pub pause: f32,
+    /// Duração de cada abertura do laço na discagem decádica
+    pub pulse_break: f32,
+    /// Duração de cada fechamento do laço entre as aberturas
+    pub pulse_make: f32,
+    /// Laço fechado entre dois dígitos decádicos
+    pub interdigit_pause: f32,
+}
+
+impl Default for DialParams {
+    fn default() -> Self {
+        Self {
+            mode: DialMode::Tone,
+            tone_duration: 0.095,
+            tone_gap: 0.095,
+            level: -8.,
+            twist: 2.,
+            pause: 2.,
+            // 10 pulsos por segundo, com relação abertura/fechamento de 60/40
+            pulse_break: 0.06,
+            pulse_make: 0.04,
+            interdigit_pause: 0.7,
+        }
+    }
+}
+
+/// Trecho do sinal de discagem.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Segment {
+    /// Par de tons DTMF de amplitudes e frequências angulares dadas
+    Tone {
+        low_amplitude: f32,
+        low_omega: f32,
+        high_amplitude: f32,
+        high_omega: f32,
+    },
+    /// Laço fechado, sem sinal
+    Silence,
+    /// Laço aberto
+    Break,
+}
+
+/// Gera o sinal de discagem de um número. Dígitos, `*`, `#` e `A` a `D`
+/// são discados, `,` espera a pausa configurada e `T` e `P` mudam o método
+/// para os dígitos seguintes; os demais caracteres são ignorados, bem como
+/// os que não existem na discagem decádica.
+///
+/// Na discagem decádica, o laço aberto é representado por um nível
+/// constante e o laço fechado por silêncio: numa interface de linha com
+/// acoplamento AC, as transições viram os estalos que a central conta.
+pub struct Dialer {
+    segments: Vec<(Segment, usize)>,
+    break_amplitude: f32,
+
+    // Atributos auxiliares
+    index: usize,
+    elapsed_samples: usize,
+}
+
+impl Dialer {
+    pub fn new(sampling_period: f32, params: &DialParams, number: &str) -> Self {
+        let samples = |duration: f32| (duration / sampling_period).round() as usize;
+        let amplitude = |level: f32| 10f32.powf(level / 20.);
+        let low_amplitude = amplitude(params.level);
+        let high_amplitude = amplitude(params.level + params.twist);
+
+        let mut mode = params.mode;
+        let mut segments = vec![];
+        for c in number.chars() {
+            match c.to_ascii_uppercase() {
+                'T' => mode = DialMode::Tone,
+                'P' => mode = DialMode::Pulse,
+                ',' => segments.push((Segment::Silence, samples(params.pause))),
+                c => match mode {
+                    DialMode::Tone => {
+                        let Some((low, high)) = frequencies(c) else {
+                            continue;
+                        };
+                        let tone = Segment::Tone {
+                            low_amplitude,
+                            low_omega: 2. * PI * low * sampling_period,
+                            high_amplitude,
+                            high_omega: 2. * PI * high * sampling_period,
+                        };
+                        segments.push((tone, samples(params.tone_duration)));
+                        segments.push((Segment::Silence, samples(params.tone_gap)));
+                    }
+                    DialMode::Pulse => {
+                        // O zero é discado com dez pulsos
+                        let Some(pulses) = c.to_digit(10) else {
+                            continue;
+                        };
+                        let pulses = if pulses == 0 { 10 } else { pulses };
+                        for i in 0..pulses {
+                            if i > 0 {
+                                segments.push((Segment::Silence, samples(params.pulse_make)));
+                            }
+                            segments.push((Segment::Break, samples(params.pulse_break)));
+                        }
+                        segments.push((Segment::Silence, samples(params.interdigit_pause)));
+                    }
+                },
+            }
+        }
+
+        Self {
+            segments,
+            break_amplitude: low_amplitude,
+            index: 0,
+            elapsed_samples: 0,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.segments[self.index..]
+            .iter()
+            .all(|&(_, samples)| samples == 0)
+    }
+
+    /// Preenche o início de `out_samples` com o que resta da discagem e
+    /// devolve quantas amostras ela ocupou; depois do fim, devolve 0.
+    pub fn generate(&mut self, out_samples: &mut [f32]) -> usize {
+        let mut written = 0;
+        for out in out_samples.iter_mut() {
+            // Pula os trechos vazios
+            while self
+                .segments
+                .get(self.index)
+                .is_some_and(|&(_, samples)| samples == 0)
+            {
+                self.index += 1;
+            }
+            let Some(&(segment, samples)) = self.segments.get(self.index) else {
+                break;
+            };
+            let n = self.elapsed_samples as f32;
+            *out = match segment {
+                Segment::Tone {
+                    low_amplitude,
+                    low_omega,
+                    high_amplitude,
+                    high_omega,
+                } => {
+                    low_amplitude * (low_omega * n).sin() + high_amplitude * (high_omega * n).sin()
+                }
+                Segment::Silence => 0.,
+                Segment::Break => self.break_amplitude,
+            };
+            self.elapsed_samples += 1;
+            if self.elapsed_samples == samples {
+                self.index += 1;
+                self.elapsed_samples = 0;
+            }
+            written += 1;
+        }
+        written
+    }
+}
+
+/// Duração, em segundos, dos blocos analisados pelo detector; a resolução
+/// em frequência (50 Hz) separa as linhas vizinhas, distantes 73 Hz.
+const DETECTOR_BLOCK: f32 = 0.02;
+
+/// Fração mínima da energia do bloco que precisa estar nos dois tons.
+const DETECTOR_RATIO: f32 = 0.6;
+
+/// Quanto, em dB, o tom escolhido precisa superar os outros do seu grupo.
+const DETECTOR_RELATIVE_PEAK: f32 = 6.;
+
+/// Razão mínima entre as energias das metades do bloco para ele ser tratado
+/// como estacionário, e não como o começo ou o fim de um tom.
+const DETECTOR_STATIONARITY: f32 = 0.6;
+
+/// Parâmetros do detector, no espírito do Q.24. Durações em segundos,
+/// níveis em dB relativos à escala cheia e twist como em `DialParams`
+/// (nível do grupo alto menos o do grupo baixo).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DetectorParams {
+    /// Duração mínima do tom para o dígito ser aceito
+    pub min_duration: f32,
+    /// Silêncio mínimo entre dois dígitos
+    pub min_gap: f32,
+    /// Nível mínimo de cada um dos tons
+    pub min_level: f32,
+    /// Menor twist aceito (grupo alto atenuado pela linha)
+    pub min_twist: f32,
+    /// Maior twist aceito (grupo alto reforçado)
+    pub max_twist: f32,
+    /// Quanto as segundas harmônicas dos tons precisam estar abaixo deles;
+    /// a voz e a música são ricas em harmônicas, os tons DTMF não
+    pub harmonic_rejection: f32,
+}
+
+impl Default for DetectorParams {
+    fn default() -> Self {
+        Self {
+            min_duration: 0.04,
+            min_gap: 0.04,
+            min_level: -40.,
+            min_twist: -8.,
+            max_twist: 4.,
+            harmonic_rejection: 10.,
+        }
+    }
+}
+
+/// Dígito detectado.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DtmfEvent {
+    pub digit: char,
+    /// Início do tom, em segundos desde a criação do detector
+    pub time: f32,
+}
+
+/// Filtro de Goertzel: calcula um único termo da DFT de um bloco.
+pub(crate) struct Goertzel {
+    coefficient: f32,
+    s1: f32,
+    s2: f32,
+}
+
+impl Goertzel {
+    pub(crate) fn new(frequency: f32, sampling_period: f32) -> Self {
+        Self {
+            coefficient: 2. * (2. * PI * frequency * sampling_period).cos(),
+            s1: 0.,
+            s2: 0.,
+        }
+    }
+
+    pub(crate) fn put_sample(&mut self, sample: f32) {
+        let s0 = sample + self.coefficient * self.s1 - self.s2;
+        self.s2 = self.s1;
+        self.s1 = s0;
+    }
+
+    /// Amplitude estimada do tom ao fim de um bloco de `n` amostras; zera o
+    /// filtro para o próximo bloco.
+    pub(crate) fn take_amplitude(&mut self, n: usize) -> f32 {
+        let power = self.s1 * self.s1 + self.s2 * self.s2 - self.coefficient * self.s1 * self.s2;
+        self.s1 = 0.;
+        self.s2 = 0.;
+        2. * power.max(0.).sqrt() / n as f32
+    }
+}
+
+/// Detector de dígitos DTMF. Filtros de Goertzel nas oito frequências do
+/// Q.23 e nas segundas harmônicas do grupo alto e do tom de 941 Hz analisam
+/// blocos de 20 ms (as demais harmônicas do grupo baixo caem a menos de
+/// 75 Hz dos tons do grupo alto e não se separam deles com essa resolução;
+/// a de 941 Hz, em 1882 Hz, fica a 249 Hz do tom de 1633 Hz). Um bloco vale
+/// como dígito se um tom de cada grupo se destaca dos demais, com nível e
+/// twist dentro dos limites, harmônicas fracas e quase toda a energia do
+/// bloco. O dígito é entregue quando se repete por `min_duration` depois de
+/// um silêncio de ao menos `min_gap`.
+///
+/// Os blocos em que o sinal começa ou termina, cujas metades têm energias
+/// muito diferentes, não contam nem como tom nem como silêncio. Como os
+/// blocos não se alinham aos tons, garante-se a detecção dos tons e pausas
+/// que excedem os mínimos em um bloco.
+pub struct DtmfDetector {
+    params: DetectorParams,
+    block_samples: usize,
+    block_duration: f32,
+    on_blocks: usize,
+    off_blocks: usize,
+    fundamentals: Vec<Goertzel>,
+    harmonics: Vec<Goertzel>,
+    events: Sender<DtmfEvent>,
+
+    // Atributos auxiliares
+    amplitudes: [f32; 8],
+    harmonic_amplitudes: [f32; 5],
+    energy: [f32; 2],
+    count: usize,
+    block_index: usize,
+    last: Option<char>,
+    run_blocks: usize,
+    run_start: usize,
+    gap_blocks: usize,
+    armed: bool,
+}
+
+impl DtmfDetector {
+    pub fn new(sampling_period: f32, params: &DetectorParams, events: Sender<DtmfEvent>) -> Self {
+        let block_samples = (DETECTOR_BLOCK / sampling_period).round() as usize;
+        let block_duration = block_samples as f32 * sampling_period;
+        let blocks = |duration: f32| ((duration / block_duration).ceil() as usize).max(1);
+        let frequencies = LOW_FREQUENCIES.iter().chain(HIGH_FREQUENCIES.iter());
+        Self {
+            params: *params,
+            block_samples,
+            block_duration,
+            on_blocks: blocks(params.min_duration),
+            off_blocks: blocks(params.min_gap),
+            fundamentals: frequencies
+                .clone()
+                .map(|&f| Goertzel::new(f, sampling_period))
+                .collect(),
+            harmonics: HIGH_FREQUENCIES
+                .iter()
+                .chain(&LOW_FREQUENCIES[3..])
+                .map(|&f| Goertzel::new(2. * f, sampling_period))
+                .collect(),
+            events,
+            amplitudes: [0.; 8],
+            harmonic_amplitudes: [0.; 5],
+            energy: [0.; 2],
+            count: 0,
+            block_index: 0,
+            last: None,
+            run_blocks: 0,
+            run_start: 0,
+            gap_blocks: 0,
+            armed: true,
+        }
+    }
+
+    pub fn put_samples(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            for goertzel in self
+                .fundamentals
+                .iter_mut()
+                .chain(self.harmonics.iter_mut())
+            {
+                goertzel.put_sample(sample);
+            }
+            self.energy[2 * self.count / self.block_samples] += sample * sample;
+            self.count += 1;
+            if self.count == self.block_samples {
+                self.end_block();
+            }
+        }
+    }
+
+    fn end_block(&mut self) {
+        let n = self.block_samples;
+        // Reaproveita os buffers da criação, sem alocar a cada bloco
+        for (amplitude, goertzel) in self.amplitudes.iter_mut().zip(&mut self.fundamentals) {
+            *amplitude = goertzel.take_amplitude(n);
+        }
+        for (amplitude, goertzel) in self.harmonic_amplitudes.iter_mut().zip(&mut self.harmonics) {
+            *amplitude = goertzel.take_amplitude(n);
+        }
+        let [first, second] = self.energy;
+        let stationary = first.min(second) >= DETECTOR_STATIONARITY * first.max(second);
+        let power = (first + second) / n as f32;
+        let digit = if stationary {
+            self.classify(&self.amplitudes, &self.harmonic_amplitudes, power)
+        } else {
+            None
+        };
+
+        match digit {
+            Some(digit) => {
+                if self.last == Some(digit) {
+                    self.run_blocks += 1;
+                } else {
+                    self.last = Some(digit);
+                    self.run_blocks = 1;
+                    self.run_start = self.block_index;
+                }
+                self.gap_blocks = 0;
+                if self.armed && self.run_blocks >= self.on_blocks {
+                    self.armed = false;
+                    let event = DtmfEvent {
+                        digit,
+                        time: self.run_start as f32 * self.block_duration,
+                    };
+                    let _ = self.events.try_send(event);
+                }
+            }
+            None => {
+                self.last = None;
+                if stationary {
+                    self.gap_blocks += 1;
+                    if self.gap_blocks >= self.off_blocks {
+                        self.armed = true;
+                    }
+                }
+            }
+        }
+
+        self.energy = [0.; 2];
+        self.count = 0;
+        self.block_index += 1;
+    }
+
+    /// Dígito presente no bloco com as amplitudes dadas (os quatro tons do
+    /// grupo baixo seguidos dos quatro do alto), as das segundas harmônicas
+    /// do grupo alto seguidas da do tom de 941 Hz e a potência média dada.
+    fn classify(&self, amplitudes: &[f32], harmonics: &[f32], power: f32) -> Option<char> {
+        let strongest = |group: &[f32]| {
+            let (index, &peak) = group
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+            let others_ok = group.iter().enumerate().all(|(i, &amplitude)| {
+                i == index || db(peak / amplitude) >= DETECTOR_RELATIVE_PEAK
+            });
+            others_ok.then_some(index)
+        };
+        let row = strongest(&amplitudes[..4])?;
+        let col = strongest(&amplitudes[4..])?;
+        let (low, high) = (amplitudes[row], amplitudes[4 + col]);
+
+        let level_ok = db(low.min(high)) >= self.params.min_level;
+        let twist = db(high / low);
+        let twist_ok = (self.params.min_twist..=self.params.max_twist).contains(&twist);
+        let rejection = self.params.harmonic_rejection;
+        let harmonic_ok = db(high / harmonics[col]) >= rejection
+            && (row != 3 || db(low / harmonics[4]) >= rejection);
+        let ratio_ok = (low * low + high * high) / 2. >= DETECTOR_RATIO * power;
+
+        (level_ok && twist_ok && harmonic_ok && ratio_ok).then(|| digit(row, col))
+    }
+}
+
+/// Razão de amplitudes em dB.
+fn db(ratio: f32) -> f32 {
+    20. * ratio.log10()
+}
@@ -0,0 +1,123 @@
+use std::str::FromStr;
+
+/// Deslocamento somado ao módulo da amostra na lei µ, que torna os
+/// segmentos logarítmicos contíguos.
+const ULAW_BIAS: i32 = 0x84;
+
+/// Maior módulo codificável na lei µ, antes do deslocamento.
+const ULAW_CLIP: i32 = 32635;
+
+/// Fim de cada um dos oito segmentos da lei A, em amostras de 13 bits.
+const ALAW_SEGMENT_END: [i32; 8] = [0x1f, 0x3f, 0x7f, 0xff, 0x1ff, 0x3ff, 0x7ff, 0xfff];
+
+/// Lei de compressão do G.711.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Law {
+    /// Lei µ, da América do Norte e do Japão
+    ULaw,
+    /// Lei A, do resto do mundo
+    ALaw,
+}
+
+impl Law {
+    /// Comprime uma amostra entre -1 e 1, limitando-a a esse intervalo.
+    pub fn encode(self, sample: f32) -> u8 {
+        let linear = (sample.clamp(-1., 1.) * 32768.)
+            .round()
+            .min(i16::MAX as f32) as i16;
+        match self {
+            Law::ULaw => ulaw_encode(linear),
+            Law::ALaw => alaw_encode(linear),
+        }
+    }
+
+    /// Expande um código numa amostra entre -1 e 1.
+    pub fn decode(self, code: u8) -> f32 {
+        let linear = match self {
+            Law::ULaw => ulaw_decode(code),
+            Law::ALaw => alaw_decode(code),
+        };
+        linear as f32 / 32768.
+    }
+}
+
+impl FromStr for Law {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ulaw" | "mulaw" | "u" => Ok(Law::ULaw),
+            "alaw" | "a" => Ok(Law::ALaw),
+            _ => Err(format!("invalid G.711 law '{}' (expected ulaw or alaw)", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Law {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Law::ULaw => write!(f, "ulaw"),
+            Law::ALaw => write!(f, "alaw"),
+        }
+    }
+}
+
+/// Comprime uma amostra linear de 16 bits pela lei µ.
+pub fn ulaw_encode(sample: i16) -> u8 {
+    let sample = sample as i32;
+    let sign: u8 = if sample < 0 { 0x80 } else { 0 };
+    let magnitude = sample.abs().min(ULAW_CLIP) + ULAW_BIAS;
+    // O segmento é a posição do bit mais alto, a partir do bit 7
+    let exponent = 24 - (magnitude as u32).leading_zeros();
+    let mantissa = (magnitude >> (exponent + 3)) as u32 & 0x0f;
+    !(sign | (exponent << 4) as u8 | mantissa as u8)
+}
+
+/// Expande um código da lei µ numa amostra linear de 16 bits.
+pub fn ulaw_decode(code: u8) -> i16 {
+    let code = !code;
+    let exponent = (code >> 4) & 0x07;
+    let mantissa = (code & 0x0f) as i32;
+    let magnitude = (((mantissa << 3) + ULAW_BIAS) << exponent) - ULAW_BIAS;
+    if code & 0x80 != 0 {
+        -magnitude as i16
+    } else {
+        magnitude as i16
+    }
+}
+
+/// Comprime uma amostra linear de 16 bits pela lei A.
+pub fn alaw_encode(sample: i16) -> u8 {
+    // A lei A trabalha com 13 bits; os bits pares do código são invertidos
+    let sample = (sample as i32) >> 3;
+    let (magnitude, mask) = if sample >= 0 {
+        (sample, 0xd5)
+    } else {
+        (-sample - 1, 0x55)
+    };
+    let Some(segment) = ALAW_SEGMENT_END.iter().position(|&end| magnitude <= end) else {
+        return 0x7f ^ mask;
+    };
+    let shift = if segment < 2 { 1 } else { segment };
+    let code = (segment << 4) as i32 | ((magnitude >> shift) & 0x0f);
+    code as u8 ^ mask
+}
+
+/// Expande um código da lei A numa amostra linear de 16 bits.
+pub fn alaw_decode(code: u8) -> i16 {
+    let code = code ^ 0x55;
+    let segment = (code >> 4) & 0x07;
+    let mut magnitude = ((code & 0x0f) as i32) << 4;
+    magnitude += match segment {
+        0 => 8,
+        _ => 0x108,
+    };
+    if segment > 1 {
+        magnitude <<= segment - 1;
+    }
+    if code & 0x80 != 0 {
+        magnitude as i16
+    } else {
+        -magnitude as i16
+    }
+}
@@ -0,0 +1,485 @@
+use crossbeam_channel::Sender;
+use std::time::{Duration, Instant};
+
+/// Registradores S implementados (S0 a S12).
+pub const NUM_REGISTERS: usize = 13;
+
+/// Número de toques para atender automaticamente (0 desliga).
+pub const S_AUTO_ANSWER: usize = 0;
+/// Toques contados desde a última chamada atendida ou desligada.
+pub const S_RING_COUNT: usize = 1;
+/// Caractere da sequência de escape.
+pub const S_ESCAPE: usize = 2;
+/// Caractere que termina a linha de comando.
+pub const S_CR: usize = 3;
+/// Caractere de avanço de linha das respostas.
+pub const S_LF: usize = 4;
+/// Caractere de retrocesso.
+pub const S_BS: usize = 5;
+/// Espera pelo tom de discar, em segundos.
+pub const S_DIAL_TONE_WAIT: usize = 6;
+/// Espera pela portadora após discar ou atender, em segundos.
+pub const S_CARRIER_WAIT: usize = 7;
+/// Espera do caractere de pausa (`,`) na discagem, em segundos.
+pub const S_COMMA_PAUSE: usize = 8;
+/// Tempo que a portadora remota precisa durar para a conexão ser dada como
+/// estabelecida, em 1/10 s.
+pub const S_CARRIER_DETECT: usize = 9;
+/// Tempo que a portadora remota pode faltar sem derrubar a conexão, em
+/// 1/10 s.
+pub const S_CARRIER_LOSS: usize = 10;
+/// Duração de cada tom DTMF e do silêncio que o segue, em milissegundos.
+pub const S_DTMF_DURATION: usize = 11;
+/// Tempo de guarda da sequência de escape, em 1/50 s.
+pub const S_GUARD_TIME: usize = 12;
+
+const DEFAULT_REGISTERS: [u8; NUM_REGISTERS] =
+    [0, 0, b'+', b'\r', b'\n', 8, 2, 50, 2, 6, 14, 95, 50];
+
+/// Linhas de comando maiores que isso são descartadas.
+const MAX_LINE_BYTES: usize = 64;
+
+/// Respostas ao terminal, na forma verbal (ATV1) ou numérica (ATV0).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResultCode {
+    Ok,
+    Connect(usize),
+    Ring,
+    NoCarrier,
+    Error,
+    NoDialtone,
+    Busy,
+    NoAnswer,
+}
+
+impl ResultCode {
+    fn code(self) -> usize {
+        match self {
+            ResultCode::Ok => 0,
+            ResultCode::Connect(1200) => 5,
+            ResultCode::Connect(2400) => 10,
+            ResultCode::Connect(_) => 1,
+            ResultCode::Ring => 2,
+            ResultCode::NoCarrier => 3,
+            ResultCode::Error => 4,
+            ResultCode::NoDialtone => 6,
+            ResultCode::Busy => 7,
+            ResultCode::NoAnswer => 8,
+        }
+    }
+}
+
+impl std::fmt::Display for ResultCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResultCode::Ok => write!(f, "OK"),
+            ResultCode::Connect(rate) => write!(f, "CONNECT {}", rate),
+            ResultCode::Ring => write!(f, "RING"),
+            ResultCode::NoCarrier => write!(f, "NO CARRIER"),
+            ResultCode::Error => write!(f, "ERROR"),
+            ResultCode::NoDialtone => write!(f, "NO DIALTONE"),
+            ResultCode::Busy => write!(f, "BUSY"),
+            ResultCode::NoAnswer => write!(f, "NO ANSWER"),
+        }
+    }
+}
+
+/// Ações pedidas ao restante do modem.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Sair do gancho no modo de origem e discar o número dado
+    Dial(String),
+    /// Sair do gancho no modo de resposta
+    Answer,
+    /// Voltar ao gancho
+    Hangup,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Aceitando comandos, no gancho ou com a conexão suspensa por escape
+    Command,
+    /// Fora do gancho, aguardando a portadora desde o instante dado
+    Connecting(Instant),
+    /// Repassando os dados do terminal à linha
+    Online,
+}
+
+/// Interpretador de comandos Hayes (AT) entre o terminal e a linha.
+///
+/// Recebe os bytes do terminal por `put_byte`, ecoa e responde pelo canal
+/// `to_terminal` e repassa os dados ao canal `to_line` quando conectado. Os
+/// eventos da linha chegam por `ring`, `ringback`, `connected`,
+/// `call_failed` e `carrier_lost`, e o passar do tempo por `poll`. As ações
+/// que o modem deve tomar são devolvidas por esses métodos.
+pub struct Hayes {
+    to_terminal: Sender<u8>,
+    to_line: Sender<u8>,
+    registers: [u8; NUM_REGISTERS],
+    echo: bool,
+    quiet: bool,
+    verbose: bool,
+
+    // Atributos auxiliares
+    state: State,
+    connection: Option<usize>,
+    line: Vec<u8>,
+    last_byte: Option<Instant>,
+    escape_count: usize,
+    ringback: bool,
+}
+
+impl Hayes {
+    pub fn new(to_terminal: Sender<u8>, to_line: Sender<u8>) -> Self {
+        Self {
+            to_terminal,
+            to_line,
+            registers: DEFAULT_REGISTERS,
+            echo: true,
+            quiet: false,
+            verbose: true,
+            state: State::Command,
+            connection: None,
+            line: vec![],
+            last_byte: None,
+            escape_count: 0,
+            ringback: false,
+        }
+    }
+
+    pub fn register(&self, index: usize) -> u8 {
+        self.registers[index]
+    }
+
+    pub fn set_register(&mut self, index: usize, value: u8) {
+        self.registers[index] = value;
+    }
+
+    /// Indica se os bytes do terminal estão indo para a linha.
+    pub fn is_online(&self) -> bool {
+        self.state == State::Online
+    }
+
+    fn guard_time(&self) -> Duration {
+        Duration::from_millis(20 * self.registers[S_GUARD_TIME] as u64)
+    }
+
+    fn send(&self, bytes: &[u8]) {
+        for &b in bytes {
+            self.to_terminal.send(b).unwrap();
+        }
+    }
+
+    fn result(&self, code: ResultCode) {
+        if self.quiet {
+            return;
+        }
+        let cr = self.registers[S_CR];
+        let lf = self.registers[S_LF];
+        if self.verbose {
+            self.send(&[cr, lf]);
+            self.send(code.to_string().as_bytes());
+            self.send(&[cr, lf]);
+        } else {
+            self.send(code.code().to_string().as_bytes());
+            self.send(&[cr]);
+        }
+    }
+
+    /// Trata um byte vindo do terminal no instante `now`.
+    pub fn put_byte(&mut self, byte: u8, now: Instant) -> Option<Action> {
+        let idle = self
+            .last_byte
+            .map_or(Duration::MAX, |last| now.duration_since(last));
+        self.last_byte = Some(now);
+
+        match self.state {
+            State::Online => {
+                // A sequência de escape só vale precedida de silêncio de
+                // pelo menos o tempo de guarda e com os caracteres separados
+                // por menos que ele; ela também segue para a linha
+                let guard_time = self.guard_time();
+                self.escape_count = match self.escape_count {
+                    _ if byte != self.registers[S_ESCAPE] => 0,
+                    0 if idle >= guard_time => 1,
+                    1 | 2 if idle < guard_time => self.escape_count + 1,
+                    _ => 0,
+                };
+                self.to_line.send(byte).unwrap();
+                None
+            }
+            State::Connecting(_) => {
+                // Qualquer tecla interrompe a discagem ou o atendimento
+                self.hangup();
+                self.result(ResultCode::NoCarrier);
+                Some(Action::Hangup)
+            }
+            State::Command => self.put_command_byte(byte, now),
+        }
+    }
+
+    fn put_command_byte(&mut self, byte: u8, now: Instant) -> Option<Action> {
+        if self.echo {
+            self.send(&[byte]);
+        }
+        if byte == self.registers[S_CR] {
+            let line = std::mem::take(&mut self.line);
+            return self.execute(&line, now);
+        }
+        if byte == self.registers[S_BS] {
+            self.line.pop();
+        } else if byte == self.registers[S_LF] {
+            // ignora o avanço de linha que alguns terminais mandam após o CR
+        } else if self.line.len() < MAX_LINE_BYTES {
+            self.line.push(byte);
+        }
+        None
+    }
+
+    /// Verifica os prazos que dependem do tempo: o silêncio após a
+    /// sequência de escape e a espera pela portadora.
+    pub fn poll(&mut self, now: Instant) -> Option<Action> {
+        match self.state {
+            State::Online if self.escape_count == 3 => {
+                let last = self.last_byte?;
+                if now.duration_since(last) >= self.guard_time() {
+                    self.escape_count = 0;
+                    self.state = State::Command;
+                    self.result(ResultCode::Ok);
+                }
+                None
+            }
+            State::Connecting(since) => {
+                let wait = Duration::from_secs(self.registers[S_CARRIER_WAIT] as u64);
+                if now.duration_since(since) >= wait {
+                    // Se o outro lado tocou sem atender, a resposta é
+                    // NO ANSWER
+                    let code = if self.ringback {
+                        ResultCode::NoAnswer
+                    } else {
+                        ResultCode::NoCarrier
+                    };
+                    return self.call_failed(code);
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// A linha tocou; atende se o número de toques chegar a S0.
+    pub fn ring(&mut self, now: Instant) -> Option<Action> {
+        if self.state != State::Command || self.connection.is_some() {
+            return None;
+        }
+        self.result(ResultCode::Ring);
+        let count = self.registers[S_RING_COUNT].saturating_add(1);
+        self.registers[S_RING_COUNT] = count;
+        let auto_answer = self.registers[S_AUTO_ANSWER];
+        if auto_answer > 0 && count >= auto_answer {
+            return Some(self.answer(now));
+        }
+        None
+    }
+
+    /// Disca o número dado, como o comando `ATD`.
+    pub fn dial(&mut self, number: &str, now: Instant) -> Action {
+        let number = number
+            .chars()
+            .map(|c| c.to_ascii_uppercase())
+            .filter(|c| c.is_ascii_digit() || "*#,ABCDTP".contains(*c))
+            .collect();
+        self.state = State::Connecting(now);
+        self.ringback = false;
+        Action::Dial(number)
+    }
+
+    /// Atende a linha, como o comando `ATA`.
+    pub fn answer(&mut self, now: Instant) -> Action {
+        self.registers[S_RING_COUNT] = 0;
+        self.state = State::Connecting(now);
+        Action::Answer
+    }
+
+    /// O tom de chamada foi ouvido: o outro lado está tocando.
+    pub fn ringback(&mut self) {
+        if let State::Connecting(_) = self.state {
+            self.ringback = true;
+        }
+    }
+
+    /// A chamada em curso falhou pelo motivo dado (tom de ocupado, falta de
+    /// tom de discar etc.); desliga.
+    pub fn call_failed(&mut self, code: ResultCode) -> Option<Action> {
+        let State::Connecting(_) = self.state else {
+            return None;
+        };
+        self.hangup();
+        self.result(code);
+        Some(Action::Hangup)
+    }
+
+    /// A portadora remota foi estabelecida, a `rate` bit/s.
+    pub fn connected(&mut self, rate: usize) {
+        if let State::Connecting(_) = self.state {
+            self.connection = Some(rate);
+            self.escape_count = 0;
+            self.state = State::Online;
+            self.result(ResultCode::Connect(rate));
+        }
+    }
+
+    /// A portadora remota sumiu; desliga se havia conexão.
+    pub fn carrier_lost(&mut self) -> Option<Action> {
+        self.connection?;
+        self.hangup();
+        self.result(ResultCode::NoCarrier);
+        Some(Action::Hangup)
+    }
+
+    fn hangup(&mut self) {
+        self.state = State::Command;
+        self.connection = None;
+        self.escape_count = 0;
+        self.ringback = false;
+    }
+
+    fn reset(&mut self) {
+        self.registers = DEFAULT_REGISTERS;
+        self.echo = true;
+        self.quiet = false;
+        self.verbose = true;
+    }
+
+    /// Executa uma linha de comando completa, sem o CR.
+    fn execute(&mut self, line: &[u8], now: Instant) -> Option<Action> {
+        let line: Vec<u8> = line
+            .iter()
+            .filter(|b| !b.is_ascii_whitespace())
+            .map(|b| b.to_ascii_uppercase())
+            .collect();
+        let Some(commands) = line.strip_prefix(b"AT") else {
+            if !line.is_empty() {
+                self.result(ResultCode::Error);
+            }
+            return None;
+        };
+
+        let mut action = None;
+        let mut rest = commands;
+        while let Some((&command, args)) = rest.split_first() {
+            rest = args;
+            let ok = match command {
+                b'D' => {
+                    // O restante da linha é o número; só os dígitos, os
+                    // sinais de discagem e os modificadores de tom (T) e pulso
+                    // (P) interessam
+                    let number = String::from_utf8_lossy(rest);
+                    rest = &[];
+                    action = Some(self.dial(&number, now));
+                    true
+                }
+                b'A' => {
+                    action = Some(self.answer(now));
+                    true
+                }
+                b'H' => {
+                    let value = take_number(&mut rest);
+                    if value.unwrap_or(0) == 0 {
+                        if self.connection.is_some() {
+                            action = Some(Action::Hangup);
+                        }
+                        self.hangup();
+                        self.registers[S_RING_COUNT] = 0;
+                        true
+                    } else {
+                        false
+                    }
+                }
+                b'O' => {
+                    take_number(&mut rest);
+                    match self.connection {
+                        Some(rate) => {
+                            self.state = State::Online;
+                            self.result(ResultCode::Connect(rate));
+                            // Os comandos depois do O não são executados, mas
+                            // a ação pedida antes dele na mesma linha vale
+                            return action;
+                        }
+                        None => false,
+                    }
+                }
+                b'Z' => {
+                    take_number(&mut rest);
+                    if self.connection.is_some() {
+                        action = Some(Action::Hangup);
+                    }
+                    self.hangup();
+                    self.reset();
+                    true
+                }
+                b'E' => set_flag(&mut self.echo, take_number(&mut rest)),
+                b'Q' => set_flag(&mut self.quiet, take_number(&mut rest)),
+                b'V' => set_flag(&mut self.verbose, take_number(&mut rest)),
+                b'S' => self.register_command(&mut rest),
+                _ => false,
+            };
+            if !ok {
+                self.result(ResultCode::Error);
+                return action;
+            }
+            if let State::Connecting(_) = self.state {
+                // O resultado da discagem ou do atendimento vem depois
+                return action;
+            }
+        }
+        self.result(ResultCode::Ok);
+        action
+    }
+
+    /// `Sn=v` altera e `Sn?` consulta um registrador.
+    fn register_command(&mut self, rest: &mut &[u8]) -> bool {
+        let Some(index) = take_number(rest).filter(|&n| n < NUM_REGISTERS) else {
+            return false;
+        };
+        match rest.split_first() {
+            Some((b'=', args)) => {
+                *rest = args;
+                match take_number(rest).map(u8::try_from) {
+                    Some(Ok(value)) => {
+                        self.registers[index] = value;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            Some((b'?', args)) => {
+                *rest = args;
+                let (cr, lf) = (self.registers[S_CR], self.registers[S_LF]);
+                self.send(&[cr, lf]);
+                self.send(format!("{:03}", self.registers[index]).as_bytes());
+                self.send(&[cr, lf]);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Consome os dígitos decimais do início de `rest`.
+fn take_number(rest: &mut &[u8]) -> Option<usize> {
+    let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+    let (number, tail) = rest.split_at(digits);
+    *rest = tail;
+    std::str::from_utf8(number).ok()?.parse().ok()
+}
+
+/// Comandos de chave liga/desliga, como `E0`/`E1`; sem argumento vale 0.
+fn set_flag(flag: &mut bool, value: Option<usize>) -> bool {
+    match value.unwrap_or(0) {
+        0 => *flag = false,
+        1 => *flag = true,
+        _ => return false,
+    }
+    true
+}
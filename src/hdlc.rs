@@ -0,0 +1,355 @@
+use crossbeam_channel::{Sender, TrySendError};
+use std::collections::VecDeque;
+
+/// Delimitador de quadro HDLC (01111110).
+pub const FLAG: u8 = 0x7e;
+
+/// Menor quadro aceito pelo receptor: um byte de conteúdo mais o FCS.
+const MIN_FRAME_BYTES: usize = 3;
+
+/// Quadros maiores que isso são descartados (o AX.25 não passa de ~330 bytes).
+const MAX_FRAME_BYTES: usize = 2048;
+
+/// FCS do HDLC/X.25 (CRC-16-CCITT refletido, valor inicial e complemento
+/// final 0xffff), transmitido com o byte menos significativo primeiro.
+pub fn crc16_ccitt(data: &[u8]) -> u16 {
+    let mut crc = 0xffffu16;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x8408
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Contadores acumulados pelo receptor desde sua criação.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HdlcStats {
+    pub frames: usize,
+    pub fcs_errors: usize,
+    pub aborts: usize,
+    pub overruns: usize,
+}
+
+/// Recebe a saída do demodulador (um nível por amostra), recupera o relógio
+/// de bit, desfaz o NRZI e o bit stuffing e entrega os quadros com FCS
+/// válido, já sem o FCS.
+pub struct HdlcRx {
+    nrzi: NrziRx,
+    deframer: HdlcDeframer,
+}
+
+impl HdlcRx {
+    pub fn new(samples_per_symbol: usize, sender: Sender<Vec<u8>>) -> Self {
+        Self {
+            nrzi: NrziRx::new(samples_per_symbol),
+            deframer: HdlcDeframer::new(sender),
+        }
+    }
+
+    pub fn stats(&self) -> HdlcStats {
+        self.deframer.stats()
+    }
+
+    pub fn put_samples(&mut self, buffer: &[u8]) {
+        self.nrzi
+            .put_samples(buffer, |bit| self.deframer.put_bit(bit));
+    }
+}
+
+/// Primeira etapa do `HdlcRx`: recupera o relógio de bit da saída do
+/// demodulador e desfaz o NRZI, sem alocar memória, o que permite rodá-la na
+/// callback de áudio e deixar o `HdlcDeframer` para outra thread.
+pub struct NrziRx {
+    samples_per_symbol: usize,
+    phase: f32,
+    last_sample: u8,
+    last_level: u8,
+}
+
+impl NrziRx {
+    pub fn new(samples_per_symbol: usize) -> Self {
+        Self {
+            samples_per_symbol,
+            phase: 0.0,
+            last_sample: 1,
+            last_level: 1,
+        }
+    }
+
+    /// Entrega a `put_bit` os bits recuperados de `buffer`, um nível por
+    /// amostra.
+    pub fn put_samples(&mut self, buffer: &[u8], mut put_bit: impl FnMut(u8)) {
+        let sps = self.samples_per_symbol as f32;
+        let mid = sps / 2.;
+
+        for &sample in buffer {
+            // A cada transição o instante estimado de início do bit é puxado
+            // pela metade do erro, o que segue desvios de relógio sem deixar
+            // que um único pico de ruído desloque a amostragem inteira.
+            if sample != self.last_sample {
+                let error = if self.phase < mid {
+                    self.phase
+                } else {
+                    self.phase - sps
+                };
+                self.phase -= 0.5 * error;
+                if self.phase < 0. {
+                    self.phase += sps;
+                }
+                self.last_sample = sample;
+            }
+
+            let previous = self.phase;
+            self.phase += 1.;
+            if previous < mid && self.phase >= mid {
+                // NRZI: ausência de transição é 1, transição é 0
+                let bit = (sample == self.last_level) as u8;
+                self.last_level = sample;
+                put_bit(bit);
+            }
+            if self.phase >= sps {
+                self.phase -= sps;
+            }
+        }
+    }
+}
+
+/// Segunda etapa do `HdlcRx`: desfaz o bit stuffing dos bits já
+/// recuperados e entrega os quadros com FCS válido, já sem o FCS.
+pub struct HdlcDeframer {
+    sender: Sender<Vec<u8>>,
+    stats: HdlcStats,
+    ones: usize,
+    in_frame: bool,
+    frame: Vec<u8>,
+    byte: u8,
+    bit_count: usize,
+}
+
+impl HdlcDeframer {
+    pub fn new(sender: Sender<Vec<u8>>) -> Self {
+        Self {
+            sender,
+            stats: HdlcStats::default(),
+            ones: 0,
+            in_frame: false,
+            frame: Vec::with_capacity(MAX_FRAME_BYTES),
+            byte: 0,
+            bit_count: 0,
+        }
+    }
+
+    pub fn stats(&self) -> HdlcStats {
+        self.stats
+    }
+
+    pub fn put_bit(&mut self, bit: u8) {
+        if bit == 1 {
+            self.ones += 1;
+            if self.ones >= 7 {
+                // Linha em repouso depois da flag de fechamento não é abort
+                if self.in_frame && (self.bit_count > 0 || !self.frame.is_empty()) {
+                    self.stats.aborts += 1;
+                }
+                self.in_frame = false;
+            }
+            return;
+        }
+
+        match self.ones {
+            // Zero inserido pelo bit stuffing: os cinco 1s são dados
+            5 => {
+                self.ones = 0;
+                for _ in 0..5 {
+                    self.push_data_bit(1);
+                }
+            }
+            // Flag: o 0 que a abriu já entrou como dado e sobra sozinho no
+            // byte parcial se o quadro estava alinhado
+            6 => {
+                self.ones = 0;
+                if self.in_frame && self.bit_count == 1 {
+                    self.end_frame();
+                }
+                self.start_frame();
+            }
+            n if n < 5 => {
+                self.ones = 0;
+                for _ in 0..n {
+                    self.push_data_bit(1);
+                }
+                self.push_data_bit(0);
+            }
+            // Depois de um abort só uma nova flag reabre o quadro
+            _ => {
+                self.ones = 0;
+            }
+        }
+    }
+
+    fn start_frame(&mut self) {
+        self.in_frame = true;
+        self.frame.clear();
+        self.byte = 0;
+        self.bit_count = 0;
+    }
+
+    fn push_data_bit(&mut self, bit: u8) {
+        if !self.in_frame {
+            return;
+        }
+
+        self.byte |= bit << self.bit_count;
+        self.bit_count += 1;
+        if self.bit_count == 8 {
+            if self.frame.len() == MAX_FRAME_BYTES {
+                self.stats.aborts += 1;
+                self.in_frame = false;
+                return;
+            }
+            self.frame.push(self.byte);
+            self.byte = 0;
+            self.bit_count = 0;
+        }
+    }
+
+    fn end_frame(&mut self) {
+        if self.frame.len() < MIN_FRAME_BYTES {
+            // Flags consecutivas ou lixo entre elas
+            return;
+        }
+
+        let n = self.frame.len() - 2;
+        let fcs = u16::from_le_bytes([self.frame[n], self.frame[n + 1]]);
+        if crc16_ccitt(&self.frame[..n]) != fcs {
+            self.stats.fcs_errors += 1;
+            return;
+        }
+
+        self.stats.frames += 1;
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(self.frame[..n].to_vec()) {
+            self.stats.overruns += 1;
+        }
+    }
+}
+
+/// Gera os níveis a modular: flags de preâmbulo, o quadro com FCS e bit
+/// stuffing, e uma flag de fechamento, tudo codificado em NRZI. Sem quadros a
+/// transmitir a linha fica em repouso, sem transições.
+pub struct HdlcTx {
+    samples_per_symbol: usize,
+    encoder: HdlcEncoder,
+    samples: VecDeque<u8>,
+}
+
+impl HdlcTx {
+    pub fn new(samples_per_symbol: usize) -> Self {
+        Self {
+            samples_per_symbol,
+            encoder: HdlcEncoder::new(),
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Número de flags transmitidas antes de cada quadro, para dar tempo ao
+    /// receptor de detectar a portadora e sincronizar o relógio.
+    pub fn set_preamble_flags(&mut self, flags: usize) {
+        self.encoder.set_preamble_flags(flags);
+    }
+
+    pub fn put_frame(&mut self, frame: &[u8]) {
+        let samples_per_symbol = self.samples_per_symbol;
+        let samples = &mut self.samples;
+        self.encoder.encode(frame, |level| {
+            for _ in 0..samples_per_symbol {
+                samples.push_back(level);
+            }
+        });
+    }
+
+    pub fn get_samples(&mut self, buffer: &mut [u8]) {
+        for sample in buffer.iter_mut() {
+            *sample = self.samples.pop_front().unwrap_or(self.encoder.level());
+        }
+    }
+}
+
+/// Codificador do `HdlcTx`, com um nível de linha por bit em vez de um por
+/// amostra; não aloca memória, de modo que os quadros podem ser montados
+/// fora da callback de áudio e passados a ela já como níveis.
+pub struct HdlcEncoder {
+    preamble_flags: usize,
+    level: u8,
+}
+
+impl Default for HdlcEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HdlcEncoder {
+    pub fn new() -> Self {
+        Self {
+            preamble_flags: 25,
+            level: 1,
+        }
+    }
+
+    /// Ver `HdlcTx::set_preamble_flags`.
+    pub fn set_preamble_flags(&mut self, flags: usize) {
+        self.preamble_flags = flags.max(1);
+    }
+
+    /// Nível em que a linha fica em repouso depois do último quadro.
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    /// Entrega a `put_level` os níveis NRZI do quadro, com as flags de
+    /// preâmbulo e de fechamento.
+    pub fn encode(&mut self, frame: &[u8], mut put_level: impl FnMut(u8)) {
+        let mut put_bit = |level: &mut u8, bit: u8| {
+            if bit == 0 {
+                *level ^= 1;
+            }
+            put_level(*level);
+        };
+
+        let mut level = self.level;
+        for _ in 0..self.preamble_flags {
+            for i in 0..8 {
+                put_bit(&mut level, (FLAG >> i) & 1);
+            }
+        }
+
+        let fcs = crc16_ccitt(frame).to_le_bytes();
+        let mut ones = 0;
+        for &byte in frame.iter().chain(fcs.iter()) {
+            for i in 0..8 {
+                let bit = (byte >> i) & 1;
+                put_bit(&mut level, bit);
+                if bit == 1 {
+                    ones += 1;
+                    if ones == 5 {
+                        put_bit(&mut level, 0);
+                        ones = 0;
+                    }
+                } else {
+                    ones = 0;
+                }
+            }
+        }
+
+        for i in 0..8 {
+            put_bit(&mut level, (FLAG >> i) & 1);
+        }
+        self.level = level;
+    }
+}
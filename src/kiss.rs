@@ -0,0 +1,109 @@
+/// Delimitadores e escapes do protocolo KISS.
+pub const FEND: u8 = 0xc0;
+pub const FESC: u8 = 0xdb;
+pub const TFEND: u8 = 0xdc;
+pub const TFESC: u8 = 0xdd;
+
+/// Comandos (nibble inferior do primeiro byte do quadro).
+pub const CMD_DATA: u8 = 0x00;
+pub const CMD_TX_DELAY: u8 = 0x01;
+pub const CMD_PERSISTENCE: u8 = 0x02;
+pub const CMD_SLOT_TIME: u8 = 0x03;
+pub const CMD_TX_TAIL: u8 = 0x04;
+pub const CMD_FULL_DUPLEX: u8 = 0x05;
+pub const CMD_SET_HARDWARE: u8 = 0x06;
+pub const CMD_RETURN: u8 = 0xff;
+
+/// Bytes acumulados sem FEND além disso são descartados.
+const MAX_FRAME_BYTES: usize = 4096;
+
+/// Quadro decodificado, com os dados emprestados do buffer do decodificador.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KissFrame<'a> {
+    pub port: u8,
+    pub command: u8,
+    pub data: &'a [u8],
+}
+
+/// Codifica um quadro de dados recebido do rádio para envio ao host.
+pub fn encode(port: u8, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 3);
+    out.push(FEND);
+    out.push((port << 4) | CMD_DATA);
+    for &byte in data {
+        match byte {
+            FEND => out.extend_from_slice(&[FESC, TFEND]),
+            FESC => out.extend_from_slice(&[FESC, TFESC]),
+            _ => out.push(byte),
+        }
+    }
+    out.push(FEND);
+    out
+}
+
+/// Decodifica o fluxo de bytes vindo do host, um byte por vez, num buffer
+/// de tamanho fixo alocado na criação.
+pub struct KissDecoder {
+    buffer: Vec<u8>,
+    escaped: bool,
+    /// O buffer guarda o quadro devolvido por último e é limpo no próximo byte
+    closed: bool,
+}
+
+impl Default for KissDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KissDecoder {
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::with_capacity(MAX_FRAME_BYTES),
+            escaped: false,
+            closed: false,
+        }
+    }
+
+    /// Devolve um quadro quando `byte` fecha um quadro não vazio.
+    pub fn put_byte(&mut self, byte: u8) -> Option<KissFrame<'_>> {
+        if self.closed {
+            self.buffer.clear();
+            self.closed = false;
+        }
+        match byte {
+            FEND => {
+                self.escaped = false;
+                self.closed = true;
+                let (&first, data) = self.buffer.split_first()?;
+                let (port, command) = if first == CMD_RETURN {
+                    (0, CMD_RETURN)
+                } else {
+                    (first >> 4, first & 0x0f)
+                };
+                Some(KissFrame {
+                    port,
+                    command,
+                    data,
+                })
+            }
+            FESC => {
+                self.escaped = true;
+                None
+            }
+            _ => {
+                let byte = match (self.escaped, byte) {
+                    (true, TFEND) => FEND,
+                    (true, TFESC) => FESC,
+                    _ => byte,
+                };
+                self.escaped = false;
+                if self.buffer.len() == MAX_FRAME_BYTES {
+                    self.buffer.clear();
+                }
+                self.buffer.push(byte);
+                None
+            }
+        }
+    }
+}
@@ -1,2 +1,18 @@
 pub mod v21;
+pub mod v22;
+pub mod v22bis;
 pub mod uart;
+pub mod hdlc;
+pub mod kiss;
+pub mod ax25;
+pub mod connection;
+pub mod hayes;
+pub mod dtmf;
+pub mod call_progress;
+pub mod audio;
+pub mod g711;
+pub mod wav;
+pub mod pcm;
+pub mod loopback;
+pub mod resample;
+pub mod link;
@@ -0,0 +1,635 @@
+use crate::call_progress::{CallProgress, CallProgressEvent, Country};
+use crate::dtmf::Dialer;
+use crate::hdlc::{HdlcDeframer, HdlcEncoder, NrziRx};
+use crate::kiss::{self, KissDecoder};
+use crate::resample::Resampler;
+use crate::uart::{Framing, SamplingMode, UartRx, UartTx};
+use crate::v21::{AnswerTone, AnswerToneDetector, CarrierParams, Standard, V21RX, V21TX};
+use crate::v22::{GuardTone, V22RX, V22TX};
+use crate::v22bis::{V22bisRX, V22bisTX};
+use crossbeam_channel::Sender;
+use rtrb::{Consumer, Producer, PushError, RingBuffer};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Produz os níveis de linha a modular (saída da UART ou do HDLC), tirando
+/// do anel o que veio do terminal: bytes para a UART ou, no Bell 202, os
+/// níveis de bit já codificados pelo `KissFramer`.
+pub type BitSource = Box<dyn FnMut(&mut Consumer<u8>, &mut [u8]) + Send>;
+
+/// Consome os níveis de linha demodulados (entrada da UART ou do HDLC).
+pub type BitSink = Box<dyn FnMut(&[u8]) + Send>;
+
+/// Converte níveis de linha em amostras de áudio (V21TX, V22TX ou V22bisTX).
+pub type Modulator = Box<dyn FnMut(&[u8], &mut [f32]) + Send>;
+
+/// Converte amostras de áudio em níveis de linha (V21RX, V22RX ou V22bisRX);
+/// devolve se havia portadora ao fim do bloco.
+pub type Demodulator = Box<dyn FnMut(&[f32], &mut [u8]) -> bool + Send>;
+
+/// Metade transmissora da ligação em curso.
+pub struct TxLink {
+    pub bits: BitSource,
+    pub modulator: Modulator,
+}
+
+/// Metade receptora da ligação em curso, com o analisador de progressão de
+/// chamada de quem origina.
+pub struct RxLink {
+    pub bits: BitSink,
+    pub demodulator: Demodulator,
+    pub call_progress: Option<CallProgress>,
+}
+
+/// Vagas de cada anel da troca de metades; o lado de comandos troca a
+/// ligação poucas vezes por chamada.
+const HANDOFF_SLOTS: usize = 4;
+
+/// Espera do lado de comandos enquanto a callback não adota as metades já
+/// instaladas.
+const HANDOFF_WAIT: Duration = Duration::from_millis(5);
+
+/// Cria a troca de uma metade da ligação entre o lado de comandos e a
+/// callback de áudio, começando no gancho.
+///
+/// A troca usa dois anéis sem trava: por um a metade nova vai para a
+/// callback, e pelo outro a antiga volta, para ser liberada pelo lado de
+/// comandos. Assim a callback nunca bloqueia nem libera memória.
+pub fn link_handoff<T>() -> (LinkSender<T>, LinkSlot<T>) {
+    let (install, to_install) = RingBuffer::new(HANDOFF_SLOTS);
+    let (to_retire, retired) = RingBuffer::new(HANDOFF_SLOTS);
+    let sender = LinkSender { install, retired };
+    let slot = LinkSlot {
+        current: None,
+        install: to_install,
+        retired: to_retire,
+    };
+    (sender, slot)
+}
+
+/// Ponta da troca do lado de comandos, que monta e desfaz a ligação.
+pub struct LinkSender<T> {
+    install: Producer<Option<T>>,
+    retired: Consumer<Option<T>>,
+}
+
+impl<T> LinkSender<T> {
+    /// Instala uma metade na callback, ou nenhuma (`None`) com o modem no
+    /// gancho, quando o TX fica em silêncio e o RX é descartado. Libera as
+    /// metades que a callback já devolveu e espera se ela ainda não adotou
+    /// as anteriores.
+    pub fn set(&mut self, mut link: Option<T>) {
+        loop {
+            while self.retired.pop().is_ok() {}
+            match self.install.push(link) {
+                Ok(()) => return,
+                // Sem callback, como no TX descartado, não há a quem entregar
+                Err(_) if self.install.is_abandoned() => return,
+                Err(PushError::Full(back)) => link = back,
+            }
+            std::thread::sleep(HANDOFF_WAIT);
+        }
+    }
+}
+
+/// Ponta da troca da callback de áudio, com a metade em uso.
+pub struct LinkSlot<T> {
+    current: Option<T>,
+    install: Consumer<Option<T>>,
+    retired: Producer<Option<T>>,
+}
+
+impl<T> LinkSlot<T> {
+    /// Adota a metade instalada por último e devolve a que estiver em uso.
+    pub fn get(&mut self) -> Option<&mut T> {
+        // Só adota uma metade nova se houver vaga para devolver a antiga
+        while self.retired.slots() > 0 {
+            let Ok(link) = self.install.pop() else {
+                break;
+            };
+            let old = std::mem::replace(&mut self.current, link);
+            let _ = self.retired.push(old);
+        }
+        self.current.as_mut()
+    }
+
+    /// Indica se há uma metade em uso, isto é, se o modem está fora do
+    /// gancho.
+    pub fn is_linked(&self) -> bool {
+        self.current.is_some()
+    }
+}
+
+/// Taxa de amostragem do modem quando a do transporte não serve ao padrão;
+/// é múltipla das taxas de sinalização de todos eles.
+pub const MODEM_RATE: usize = 48000;
+
+/// Taxa de amostragem do modem sobre um transporte da taxa dada: a própria,
+/// se for múltipla das taxas de sinalização dos dois sentidos, ou
+/// `MODEM_RATE`, convertida de e para a do transporte.
+pub fn modem_rate(standard: Standard, transport_rate: usize) -> usize {
+    let fits = |answer| transport_rate % standard.baud_rate(answer) == 0;
+    if fits(false) && fits(true) {
+        transport_rate
+    } else {
+        MODEM_RATE
+    }
+}
+
+/// Lado de TX visto por um transporte de áudio, que converte as amostras
+/// para a taxa do transporte quando ela difere da do modem.
+///
+/// Os buffers são alocados na criação para o maior bloco do transporte e
+/// depois reaproveitados, de modo que a callback não aloca memória.
+pub struct TxAudio {
+    link: LinkSlot<TxLink>,
+    /// Bytes do terminal, num anel sem trava com um só produtor
+    from_terminal: Consumer<u8>,
+    resampler: Option<Resampler>,
+    /// Amostras já convertidas e ainda não entregues ao transporte
+    pending: Vec<f32>,
+    modem_out: Vec<f32>,
+    levels: Vec<u8>,
+}
+
+impl TxAudio {
+    /// `max_block` é o maior bloco, em amostras do transporte, que ele pede
+    /// de uma vez.
+    pub fn new(
+        link: LinkSlot<TxLink>,
+        from_terminal: Consumer<u8>,
+        standard: Standard,
+        transport_rate: usize,
+        max_block: usize,
+    ) -> Self {
+        let modem_rate = modem_rate(standard, transport_rate);
+        let mut resampler =
+            (modem_rate != transport_rate).then(|| Resampler::new(modem_rate, transport_rate));
+        let (modem_block, pending) = match resampler.as_mut() {
+            Some(resampler) => {
+                let modem_block = (max_block as f64 * resampler.ratio()).ceil() as usize;
+                resampler.reserve(modem_block);
+                // O que falta para um bloco, mais o que uma volta converte
+                (modem_block, max_block + resampler.max_output(modem_block))
+            }
+            None => (max_block, 0),
+        };
+        Self {
+            link,
+            from_terminal,
+            resampler,
+            pending: Vec::with_capacity(pending),
+            modem_out: Vec::with_capacity(modem_block),
+            levels: Vec::with_capacity(modem_block),
+        }
+    }
+
+    /// Preenche `out` com as próximas amostras de TX, na taxa do transporte.
+    pub fn process(&mut self, out: &mut [f32]) {
+        let Some(resampler) = self.resampler.as_mut() else {
+            tx_process(
+                &mut self.link,
+                &mut self.from_terminal,
+                &mut self.levels,
+                out,
+            );
+            return;
+        };
+        // O conversor só entrega cada amostra depois de receber as que a
+        // seguem dentro do filtro, então a primeira volta pode não bastar
+        while self.pending.len() < out.len() {
+            let missing = out.len() - self.pending.len();
+            let len = (missing as f64 * resampler.ratio()).ceil() as usize;
+            self.modem_out.resize(len, 0.);
+            tx_process(
+                &mut self.link,
+                &mut self.from_terminal,
+                &mut self.levels,
+                &mut self.modem_out,
+            );
+            resampler.process(&self.modem_out, &mut self.pending);
+        }
+        out.copy_from_slice(&self.pending[..out.len()]);
+        self.pending.drain(..out.len());
+    }
+
+    /// Indica se o modem está fora do gancho.
+    pub fn is_off_hook(&self) -> bool {
+        self.link.is_linked()
+    }
+}
+
+/// Gera as amostras de TX da ligação em curso; silêncio com o modem no
+/// gancho.
+fn tx_process(
+    link: &mut LinkSlot<TxLink>,
+    from_terminal: &mut Consumer<u8>,
+    levels: &mut Vec<u8>,
+    modem_out: &mut [f32],
+) {
+    modem_out.fill(0.);
+    if let Some(link) = link.get() {
+        levels.clear();
+        levels.resize(modem_out.len(), 1);
+        (link.bits)(from_terminal, levels);
+        (link.modulator)(levels, modem_out);
+    }
+}
+
+/// Lado de RX visto por um transporte de áudio, que converte as amostras
+/// para a taxa do modem quando ela difere da do transporte; como no TX, os
+/// buffers são alocados na criação e reaproveitados entre as callbacks.
+pub struct RxAudio {
+    link: LinkSlot<RxLink>,
+    carrier: Arc<AtomicBool>,
+    resampler: Option<Resampler>,
+    modem_in: Vec<f32>,
+    levels: Vec<u8>,
+}
+
+impl RxAudio {
+    /// `max_block` é o maior bloco, em amostras do transporte, que ele
+    /// entrega de uma vez.
+    pub fn new(
+        link: LinkSlot<RxLink>,
+        carrier: Arc<AtomicBool>,
+        standard: Standard,
+        transport_rate: usize,
+        max_block: usize,
+    ) -> Self {
+        let modem_rate = modem_rate(standard, transport_rate);
+        let mut resampler =
+            (modem_rate != transport_rate).then(|| Resampler::new(transport_rate, modem_rate));
+        let (modem_in, modem_block) = match resampler.as_mut() {
+            Some(resampler) => {
+                resampler.reserve(max_block);
+                let modem_block = resampler.max_output(max_block);
+                (modem_block, modem_block)
+            }
+            None => (0, max_block),
+        };
+        Self {
+            link,
+            carrier,
+            resampler,
+            modem_in: Vec::with_capacity(modem_in),
+            levels: Vec::with_capacity(modem_block),
+        }
+    }
+
+    /// Entrega à ligação as amostras de RX, na taxa do transporte.
+    pub fn process(&mut self, input: &[f32]) {
+        let Some(resampler) = self.resampler.as_mut() else {
+            rx_process(&mut self.link, &self.carrier, &mut self.levels, input);
+            return;
+        };
+        self.modem_in.clear();
+        resampler.process(input, &mut self.modem_in);
+        rx_process(
+            &mut self.link,
+            &self.carrier,
+            &mut self.levels,
+            &self.modem_in,
+        );
+    }
+}
+
+/// Entrega as amostras de RX à ligação em curso e atualiza o DCD; com o
+/// modem no gancho, elas são descartadas.
+fn rx_process(
+    link: &mut LinkSlot<RxLink>,
+    carrier: &AtomicBool,
+    levels: &mut Vec<u8>,
+    modem_in: &[f32],
+) {
+    let Some(link) = link.get() else {
+        carrier.store(false, Ordering::Relaxed);
+        return;
+    };
+
+    levels.clear();
+    levels.resize(modem_in.len(), 1);
+    carrier.store((link.demodulator)(modem_in, levels), Ordering::Relaxed);
+
+    (link.bits)(levels);
+
+    if let Some(call_progress) = link.call_progress.as_mut() {
+        call_progress.put_samples(modem_in);
+    }
+}
+
+/// Níveis de bit em trânsito entre a callback de RX do Bell 202 e o
+/// `HdlcDeframer`, mais de 3 s a 1200 baud.
+const BIT_QUEUE: usize = 4096;
+
+/// Intervalo com que o `HdlcDeframer` confere o anel de bits.
+const DEFRAMER_POLL: Duration = Duration::from_millis(10);
+
+/// Opções do modem de que dependem as metades da ligação.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinkParams {
+    pub standard: Standard,
+    pub framing: Framing,
+    /// Decisão de cada bit recebido por maioria
+    pub majority_vote: bool,
+    /// Detecção da taxa de RX pelo fluxo de bits
+    pub autobaud: bool,
+    /// Detecção de portadora dos receptores FSK
+    pub carrier: CarrierParams,
+    /// Tom de guarda do V.22 e do V.22bis, só transmitido por quem atende
+    pub guard_tone: GuardTone,
+    /// Inversões de fase no tom de resposta do V.25
+    pub answer_tone_reversals: bool,
+    /// Plano de tons da progressão de chamada de quem origina; sem ele, a
+    /// discagem é cega
+    pub country: Option<Country>,
+}
+
+/// Monta as metades da ligação, ligadas às filas e ao DCD do lado de
+/// comandos; usado pelo modem e pelos benchmarks das callbacks.
+pub struct LinkBuilder {
+    pub params: LinkParams,
+    /// Taxas de amostragem do modem, que devem ser múltiplas das taxas de
+    /// sinalização
+    pub tx_srate: usize,
+    pub rx_srate: usize,
+    /// DCD atualizado pela callback de RX
+    pub carrier: Arc<AtomicBool>,
+    /// Taxa de RX detectada pela callback de RX, zero enquanto não houver;
+    /// quem a anuncia é o lado de comandos
+    pub rx_baud_rate: Arc<AtomicUsize>,
+    /// Bytes recebidos pela UART
+    pub to_terminal: Sender<u8>,
+    /// Quadros recebidos pelo HDLC no Bell 202, já sem o FCS
+    pub frames: Sender<Vec<u8>>,
+    pub call_progress: Sender<CallProgressEvent>,
+}
+
+impl LinkBuilder {
+    /// Metade transmissora no modo de resposta ou de origem, que disca antes
+    /// se houver o que discar.
+    pub fn tx(&self, answer: bool, dialer: Option<Dialer>) -> TxLink {
+        let params = &self.params;
+        let (tx_omega1, tx_omega0) = params.standard.omegas(answer);
+        let tx_baud_rate = params.standard.baud_rate(answer);
+        let tx_srate = self.tx_srate;
+        assert!(
+            tx_srate % tx_baud_rate == 0,
+            "TX sampling rate {} is not a multiple of the baud rate {}",
+            tx_srate,
+            tx_baud_rate
+        );
+        let tx_samples_per_symbol = tx_srate / tx_baud_rate;
+        let tx_speriod = 1. / tx_srate as f32;
+
+        let bits: BitSource = if params.standard == Standard::Bell202 {
+            // Os quadros chegam do `KissFramer` como um nível por bit, e a
+            // linha fica no último nível quando não há o que transmitir
+            let mut level = 1;
+            let mut remaining = 0;
+            Box::new(move |levels: &mut Consumer<u8>, bits: &mut [u8]| {
+                for bit in bits.iter_mut() {
+                    if remaining == 0 {
+                        if let Ok(next) = levels.pop() {
+                            level = next;
+                            remaining = tx_samples_per_symbol;
+                        }
+                    }
+                    remaining = remaining.saturating_sub(1);
+                    *bit = level;
+                }
+            })
+        } else {
+            let mut uart_tx = UartTx::with_rates(tx_srate, tx_baud_rate, params.framing);
+            Box::new(move |bytes: &mut Consumer<u8>, bits: &mut [u8]| {
+                // Só tira do anel o que cabe neste bloco; o resto fica lá
+                // e, com o anel cheio, o pty espera
+                while uart_tx.queued_samples() < bits.len() {
+                    let Ok(b) = bytes.pop() else {
+                        break;
+                    };
+                    uart_tx.put_byte(b);
+                }
+                uart_tx.get_samples(bits)
+            })
+        };
+
+        // Só quem atende transmite o tom de guarda
+        let guard_tone = if answer {
+            params.guard_tone
+        } else {
+            GuardTone::None
+        };
+        let modulator: Modulator = match params.standard {
+            Standard::V22 => {
+                let mut v22_tx = V22TX::new(tx_speriod, tx_omega1, guard_tone);
+                Box::new(move |bits: &[u8], samples: &mut [f32]| v22_tx.modulate(bits, samples))
+            }
+            Standard::V22bis => {
+                let mut v22bis_tx = V22bisTX::new(tx_speriod, tx_omega1, guard_tone);
+                Box::new(move |bits: &[u8], samples: &mut [f32]| v22bis_tx.modulate(bits, samples))
+            }
+            _ => {
+                let mut v21_tx = V21TX::new(tx_speriod, tx_omega1, tx_omega0);
+                Box::new(move |bits: &[u8], samples: &mut [f32]| v21_tx.modulate(bits, samples))
+            }
+        };
+
+        let modulator: Modulator = if answer && params.standard.answer_tone() {
+            // Quem atende abre a chamada com a sequência do V.25
+            let mut answer_tone = AnswerTone::new(tx_speriod, params.answer_tone_reversals);
+            let mut modulator = modulator;
+            Box::new(move |bits: &[u8], samples: &mut [f32]| {
+                let n = answer_tone.generate(samples);
+                modulator(&bits[n..], &mut samples[n..])
+            })
+        } else if !answer && params.standard != Standard::Bell202 {
+            // Quem origina disca e só começa a transmitir depois de ouvir a
+            // portadora de quem atende
+            let carrier = self.carrier.clone();
+            let mut heard = false;
+            let mut dialer = dialer;
+            let mut modulator = modulator;
+            Box::new(move |bits: &[u8], samples: &mut [f32]| {
+                let n = dialer.as_mut().map_or(0, |dialer| dialer.generate(samples));
+                let (bits, samples) = (&bits[n..], &mut samples[n..]);
+                heard |= n == 0 && carrier.load(Ordering::Relaxed);
+                if heard {
+                    modulator(bits, samples)
+                } else {
+                    samples.fill(0.)
+                }
+            })
+        } else {
+            modulator
+        };
+
+        TxLink { bits, modulator }
+    }
+
+    /// Metade receptora no modo de resposta ou de origem.
+    pub fn rx(&self, answer: bool) -> RxLink {
+        let params = &self.params;
+        let (rx_omega1, rx_omega0) = params.standard.omegas(!answer);
+        let rx_baud_rate = params.standard.baud_rate(!answer);
+        let rx_srate = self.rx_srate;
+        assert!(
+            rx_srate % rx_baud_rate == 0,
+            "RX sampling rate {} is not a multiple of the baud rate {}",
+            rx_srate,
+            rx_baud_rate
+        );
+        let rx_samples_per_symbol = rx_srate / rx_baud_rate;
+        let rx_speriod = 1. / rx_srate as f32;
+
+        let bits: BitSink = if params.standard == Standard::Bell202 {
+            // A callback só recupera os bits; os quadros são montados fora
+            // dela, pela thread do `HdlcDeframer`
+            let (mut to_deframer, from_callback) = RingBuffer::new(BIT_QUEUE);
+            spawn_deframer(from_callback, self.frames.clone());
+            let mut nrzi_rx = NrziRx::new(rx_samples_per_symbol);
+            Box::new(move |bits: &[u8]| {
+                nrzi_rx.put_samples(bits, |bit| {
+                    let _ = to_deframer.push(bit);
+                })
+            })
+        } else {
+            let to_terminal = self.to_terminal.clone();
+            let mut uart_rx = UartRx::new(rx_samples_per_symbol, params.framing, to_terminal);
+            if params.majority_vote {
+                uart_rx.set_sampling_mode(SamplingMode::MajorityVote);
+            }
+            if params.autobaud {
+                uart_rx.set_autobaud(rx_srate);
+                self.rx_baud_rate.store(0, Ordering::Relaxed);
+            }
+            let rx_baud_rate = self.rx_baud_rate.clone();
+            let mut baud_rate = uart_rx.baud_rate();
+            Box::new(move |bits: &[u8]| {
+                uart_rx.put_samples(bits);
+
+                if baud_rate != uart_rx.baud_rate() {
+                    baud_rate = uart_rx.baud_rate();
+                    rx_baud_rate.store(baud_rate.unwrap_or(0), Ordering::Relaxed);
+                }
+            })
+        };
+
+        let demodulator: Demodulator = match params.standard {
+            Standard::V22 => {
+                let mut v22_rx = V22RX::new(rx_speriod, rx_omega1);
+                Box::new(move |samples: &[f32], bits: &mut [u8]| {
+                    v22_rx.demodulate(samples, bits);
+                    v22_rx.carrier_detected()
+                })
+            }
+            Standard::V22bis => {
+                let mut v22bis_rx = V22bisRX::new(rx_speriod, rx_omega1);
+                Box::new(move |samples: &[f32], bits: &mut [u8]| {
+                    v22bis_rx.demodulate(samples, bits);
+                    v22bis_rx.carrier_detected()
+                })
+            }
+            _ => {
+                let mut v21_rx = V21RX::new(
+                    rx_speriod,
+                    rx_samples_per_symbol,
+                    rx_omega1,
+                    rx_omega0,
+                    &params.carrier,
+                );
+                Box::new(move |samples: &[f32], bits: &mut [u8]| {
+                    v21_rx.demodulate(samples, bits);
+                    v21_rx.carrier_detected()
+                })
+            }
+        };
+
+        let demodulator: Demodulator = if params.standard.answer_tone() {
+            // O tom de resposta cai na faixa de alguns receptores; enquanto
+            // ele soa, a portadora não é considerada
+            let mut detector = AnswerToneDetector::new(rx_speriod);
+            let mut demodulator = demodulator;
+            Box::new(move |samples: &[f32], bits: &mut [u8]| {
+                detector.put_samples(samples);
+                demodulator(samples, bits) && !detector.detected()
+            })
+        } else {
+            demodulator
+        };
+
+        // Quem origina acompanha os tons da central, se o plano do país
+        // foi dado
+        let call_progress = match params.country {
+            Some(country) if !answer => Some(CallProgress::new(
+                rx_speriod,
+                country,
+                self.call_progress.clone(),
+            )),
+            _ => None,
+        };
+
+        RxLink {
+            bits,
+            demodulator,
+            call_progress,
+        }
+    }
+}
+
+/// Desfaz o bit stuffing dos bits recuperados pela callback de RX do Bell
+/// 202 e entrega os quadros; termina quando a ligação é desfeita e a ponta
+/// da callback, descartada.
+fn spawn_deframer(mut bits: Consumer<u8>, frames: Sender<Vec<u8>>) {
+    std::thread::spawn(move || {
+        let mut deframer = HdlcDeframer::new(frames);
+        loop {
+            let abandoned = bits.is_abandoned();
+            while let Ok(bit) = bits.pop() {
+                deframer.put_bit(bit);
+            }
+            if abandoned {
+                break;
+            }
+            std::thread::sleep(DEFRAMER_POLL);
+        }
+    });
+}
+
+/// Lado de TX do Bell 202 fora da callback de áudio: decodifica o KISS
+/// vindo do terminal e codifica os quadros de dados em HDLC, com um nível
+/// de linha por bit, que a callback repete por um símbolo.
+pub struct KissFramer {
+    baud_rate: usize,
+    decoder: KissDecoder,
+    encoder: HdlcEncoder,
+}
+
+impl KissFramer {
+    pub fn new(baud_rate: usize) -> Self {
+        Self {
+            baud_rate,
+            decoder: KissDecoder::new(),
+            encoder: HdlcEncoder::new(),
+        }
+    }
+
+    /// Trata um byte do terminal; se ele fecha um quadro de dados, entrega a
+    /// `put_level` os níveis a transmitir.
+    pub fn put_byte(&mut self, byte: u8, put_level: impl FnMut(u8)) {
+        let Some(frame) = self.decoder.put_byte(byte) else {
+            return;
+        };
+        match frame.command {
+            kiss::CMD_DATA => self.encoder.encode(frame.data, put_level),
+            kiss::CMD_TX_DELAY => {
+                // TXDELAY vem em unidades de 10 ms
+                let delay = frame.data.first().copied().unwrap_or(0) as usize;
+                let flags = (delay * self.baud_rate).div_ceil(800);
+                self.encoder.set_preamble_flags(flags);
+            }
+            _ => {}
+        }
+    }
+}
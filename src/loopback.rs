@@ -0,0 +1,109 @@
+use rand::SeedableRng;
+use rand_distr::{Distribution, Normal};
+use std::collections::VecDeque;
+
+/// Parâmetros da linha simulada entre dois modems do mesmo processo. Níveis
+/// em dB e atraso em segundos.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineParams {
+    /// Ganho (negativo para atenuação) aplicado ao sinal transmitido
+    pub gain: f32,
+    /// Nível RMS do ruído branco gaussiano somado na recepção, relativo à
+    /// escala cheia; `None` para uma linha sem ruído
+    pub noise: Option<f32>,
+    /// Atraso de propagação em cada sentido
+    pub delay: f32,
+    /// Quanto o relógio de amostragem da recepção está adiantado em relação
+    /// ao da transmissão, em partes por milhão
+    pub skew: f32,
+}
+
+impl Default for LineParams {
+    fn default() -> Self {
+        Self {
+            gain: 0.,
+            noise: None,
+            delay: 0.,
+            skew: 0.,
+        }
+    }
+}
+
+/// Um sentido da linha simulada. As amostras produzidas com o relógio do
+/// transmissor passam pelo ganho e pelo atraso, são reamostradas por
+/// interpolação linear para o relógio do receptor e recebem o ruído.
+pub struct LineSimulator {
+    gain: f32,
+    noise: Option<Normal<f32>>,
+    rng: rand_pcg::Pcg32,
+    delay: VecDeque<f32>,
+    /// Amostras de entrada por amostra de saída
+    step: f64,
+
+    // Atributos auxiliares
+    position: f64,
+    previous: f32,
+}
+
+impl LineSimulator {
+    /// Cria um sentido da linha; sentidos com sementes diferentes recebem
+    /// ruídos independentes.
+    pub fn new(sampling_period: f32, params: &LineParams, seed: u64) -> Self {
+        let delay_samples = (params.delay / sampling_period).round() as usize;
+        Self {
+            gain: 10f32.powf(params.gain / 20.),
+            noise: params
+                .noise
+                .map(|level| Normal::new(0., 10f32.powf(level / 20.)).unwrap()),
+            rng: rand_pcg::Pcg32::seed_from_u64(seed),
+            delay: VecDeque::from(vec![0.; delay_samples]),
+            step: 1. / (1. + params.skew as f64 * 1e-6),
+            position: 0.,
+            previous: 0.,
+        }
+    }
+
+    /// Leva pela linha as amostras transmitidas e devolve as que o receptor
+    /// amostra no mesmo intervalo; com o relógio do receptor adiantado, elas
+    /// são em média um pouco mais numerosas que as de entrada.
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        let input: Vec<f32> = samples
+            .iter()
+            .map(|&sample| {
+                self.delay.push_back(sample * self.gain);
+                self.delay.pop_front().unwrap()
+            })
+            .collect();
+
+        // A posição é relativa ao início do bloco; -1 é a última amostra do
+        // bloco anterior
+        let at = |i: isize| {
+            if i < 0 {
+                self.previous
+            } else {
+                input[i as usize]
+            }
+        };
+        let mut output = Vec::with_capacity(input.len() + 1);
+        loop {
+            let i = self.position.floor() as isize;
+            if i + 1 >= input.len() as isize {
+                break;
+            }
+            let frac = (self.position - i as f64) as f32;
+            output.push(at(i) * (1. - frac) + at(i + 1) * frac);
+            self.position += self.step;
+        }
+        self.position -= input.len() as f64;
+        if let Some(&last) = input.last() {
+            self.previous = last;
+        }
+
+        if let Some(noise) = self.noise {
+            for sample in output.iter_mut() {
+                *sample += noise.sample(&mut self.rng);
+            }
+        }
+        output
+    }
+}
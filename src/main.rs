@@ -1,31 +1,107 @@
 #[cfg_attr(unix, path = "serial_linux.rs")]
 #[cfg_attr(windows, path = "serial_windows.rs")]
 mod serial;
-mod uart;
-mod v21;
 
-use crate::serial::Serial;
-use crate::uart::{UartRx, UartTx};
-use crate::v21::{V21RX, V21TX};
+use crate::serial::{ModemControl, Serial};
+#[allow(clippy::single_component_path_imports)]
 use anyhow;
 use clap::Parser;
 use cpal::{
     traits::{DeviceTrait, HostTrait, StreamTrait},
-    BuildStreamError, FromSample, SizedSample, Stream,
+    BuildStreamError, FromSample, SizedSample, Stream, SupportedBufferSize,
 };
-use crossbeam_channel::unbounded;
-use std::sync::Mutex;
-use std::{f32::consts::PI, sync::Arc};
+use crossbeam_channel::{bounded, never, select, unbounded, Receiver, Sender};
+use modem::audio::{AudioSink, AudioSource};
+use modem::ax25;
+use modem::call_progress::{CallProgressEvent, Country};
+use modem::connection::{Connection, ConnectionEvent};
+use modem::dtmf::{DialMode, DialParams, Dialer};
+use modem::g711::Law;
+use modem::hayes::{self, Action, Hayes, ResultCode};
+use modem::kiss;
+use modem::link::{
+    link_handoff, modem_rate, KissFramer, LinkBuilder, LinkParams, LinkSender, LinkSlot, RxAudio,
+    RxLink, TxAudio, TxLink,
+};
+use modem::loopback::{LineParams, LineSimulator};
+use modem::pcm::{self, PcmEndpoint, PcmFormat, PcmInput, PcmOutput};
+use modem::uart::Framing;
+use modem::v21::{CarrierParams, Standard};
+use modem::v22::GuardTone;
+use modem::wav::{WavInput, WavOutput};
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Intervalo com que o interpretador de comandos confere seus prazos.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Duração dos blocos de áudio trocados com arquivos e fluxos e na linha
+/// simulada.
+const BLOCK: Duration = Duration::from_millis(10);
+
+/// Maior bloco, em quadros, que as callbacks dos dispositivos de áudio
+/// processam de uma vez; blocos maiores são divididos.
+const DEVICE_BLOCK: usize = 4096;
 
-const BAUD_RATE: usize = 300;
+/// Intervalo entre os toques no modem de resposta do modo loopback.
+const RING_PERIOD: Duration = Duration::from_secs(6);
 
-#[derive(Parser, Debug)]
+/// Bytes em trânsito entre o terminal e a callback de áudio em cada
+/// sentido (no TX do Bell 202, níveis de bit já em HDLC); cheia a fila de
+/// TX, o pty espera, e cheia a de RX, a UART conta um overrun.
+const LINE_QUEUE: usize = 4096;
+
+/// Tons de progressão de chamada à espera do lado de comandos; cheia a
+/// fila, a callback de RX descarta os seguintes.
+const EVENT_QUEUE: usize = 16;
+
+#[derive(Parser, Clone, Debug)]
 #[command(version, about = "Dial-up modem", long_about = None)]
 struct Opt {
-    /// Answer side
+    /// Answer at startup, as if ATA had been typed; afterwards ATD originates and ATA answers
     #[arg(short, long, default_value_t = false)]
     answer: bool,
 
+    /// Dial this number at startup, as if ATD had been typed
+    #[arg(short, long, conflicts_with = "answer")]
+    dial: Option<String>,
+
+    /// Default dialling method: tone (DTMF) or pulse; T and P in the number override it
+    #[arg(long, default_value_t = DialMode::default())]
+    dial_mode: DialMode,
+
+    /// Country tone plan (na or br) for dial tone, busy, ringback and SIT detection; without it, dialling is blind
+    #[arg(long)]
+    country: Option<Country>,
+
+    /// DTMF low-group tone level, in dB relative to full scale
+    #[arg(long, default_value_t = DialParams::default().level, allow_negative_numbers = true)]
+    dtmf_level: f32,
+
+    /// DTMF high-group level minus low-group level (twist), in dB
+    #[arg(long, default_value_t = DialParams::default().twist, allow_negative_numbers = true)]
+    dtmf_twist: f32,
+
+    /// FSK carrier-on threshold, as an in-band signal-to-noise ratio in dB
+    #[arg(long, default_value_t = CarrierParams::default().on_threshold, allow_negative_numbers = true)]
+    carrier_on: f32,
+
+    /// FSK carrier-off threshold, as an in-band signal-to-noise ratio in dB; below --carrier-on for hysteresis
+    #[arg(long, default_value_t = CarrierParams::default().off_threshold, allow_negative_numbers = true)]
+    carrier_off: f32,
+
+    /// Weakest input power the FSK receiver's AGC compensates for, in dB relative to full scale
+    #[arg(long, default_value_t = CarrierParams::default().min_level, allow_negative_numbers = true)]
+    carrier_min_level: f32,
+
+    /// Modulation standard: v21, bell103, v23, bell202 (AFSK1200 packet, KISS on the pty), v22 or v22bis
+    #[arg(short = 'm', long, default_value_t = Standard::default())]
+    standard: Standard,
+
     /// Audio device to use for RX
     #[arg(short, long, default_value_t = String::from("default"))]
     rxdev: String,
@@ -34,127 +110,948 @@ struct Opt {
     #[arg(short, long, default_value_t = String::from("default"))]
     txdev: String,
 
+    /// Read RX audio from this WAV file (PCM or float, first channel) instead of the RX device
+    #[arg(long, group = "rx_stream")]
+    rx_wav: Option<PathBuf>,
+
+    /// Write TX audio to this WAV file instead of the TX device
+    #[arg(long, group = "tx_stream")]
+    tx_wav: Option<PathBuf>,
+
+    /// Sampling rate of the TX WAV file
+    #[arg(long, default_value_t = 48000)]
+    tx_wav_rate: usize,
+
+    /// Write the TX WAV file as 32-bit float instead of 16-bit PCM
+    #[arg(long, default_value_t = false)]
+    tx_wav_float: bool,
+
+    /// Write the TX WAV file as 8-bit G.711, ulaw or alaw, instead of 16-bit PCM
+    #[arg(long, conflicts_with = "tx_wav_float")]
+    tx_wav_g711: Option<Law>,
+
+    /// Read raw RX audio from stdin (-), a UNIX socket (unix:PATH), or a file or FIFO, instead of the RX device; the modem exits at end of stream
+    #[arg(long, group = "rx_stream")]
+    rx_pcm: Option<PcmEndpoint>,
+
+    /// Write raw TX audio to stdout (-), a UNIX socket (unix:PATH, shared with --rx-pcm if the same), or a file or FIFO, instead of the TX device
+    #[arg(long, group = "tx_stream")]
+    tx_pcm: Option<PcmEndpoint>,
+
+    /// Raw PCM sample format: s16le, s16be, u8, f32le, or G.711 ulaw or alaw
+    #[arg(long, default_value_t = PcmFormat::default())]
+    pcm_format: PcmFormat,
+
+    /// Raw PCM sampling rate
+    #[arg(long, default_value_t = 8000)]
+    pcm_rate: usize,
+
+    /// Process the RX WAV or PCM input faster than real time, with the link up from the start, no AT commands and TX discarded unless --tx-wav or --tx-pcm is given
+    #[arg(long, default_value_t = false, requires = "rx_stream")]
+    fast: bool,
+
+    /// Run an originate and an answer modem connected by a simulated line, each with its own pty, instead of using audio devices; ATD on the originate pty rings the answer pty
+    #[arg(long, default_value_t = false, conflicts_with_all = ["answer", "country", "rx_stream", "tx_stream"])]
+    loopback: bool,
+
+    /// Sampling rate of the simulated line in loopback mode; modems resample to 48000 Hz when it is not a multiple of the baud rates
+    #[arg(long, default_value_t = 48000)]
+    loopback_rate: usize,
+
+    /// Simulated line gain, in dB
+    #[arg(long, default_value_t = LineParams::default().gain, allow_negative_numbers = true)]
+    line_gain: f32,
+
+    /// Simulated line white noise RMS level, in dB relative to full scale; no noise if absent
+    #[arg(long, allow_negative_numbers = true)]
+    line_noise: Option<f32>,
+
+    /// Simulated line delay in each direction, in milliseconds
+    #[arg(long, default_value_t = 1000. * LineParams::default().delay)]
+    line_delay: f32,
+
+    /// Sampling clock offset of the answer modem relative to the originate one, in parts per million
+    #[arg(long, default_value_t = LineParams::default().skew, allow_negative_numbers = true)]
+    line_skew: f32,
+
     /// Serial device (Windows-only)
     #[arg(short, long, default_value_t = String::from("\\\\.\\COM3"))]
     serdev: String,
+
+    /// UART framing (data bits, parity N/E/O/M/S, stop bits), e.g. 8N1, 7E1, 7O2
+    #[arg(short, long, default_value_t = Framing::default())]
+    framing: Framing,
+
+    /// Decide each received bit by majority vote, re-centring on bit edges
+    #[arg(long, default_value_t = false)]
+    majority_vote: bool,
+
+    /// Detect the RX baud rate from the incoming bit stream
+    #[arg(long, default_value_t = false)]
+    autobaud: bool,
+
+    /// V.22/V.22bis guard tone sent by the answer side: none, 550 or 1800
+    #[arg(long, default_value_t = GuardTone::default())]
+    guard_tone: GuardTone,
+
+    /// Add phase reversals to the V.25 answer tone, disabling network echo cancellers
+    #[arg(long, default_value_t = false)]
+    answer_tone_reversals: bool,
 }
 
 fn main() -> anyhow::Result<()> {
     let opt = Opt::parse();
+    if opt.loopback {
+        return loopback(opt);
+    }
 
     let host = cpal::default_host();
+    let (pcm_in, pcm_out) = pcm::connect(opt.rx_pcm.as_ref(), opt.tx_pcm.as_ref())?;
 
-    let rxdev = if opt.rxdev == "default" {
-        host.default_input_device()
+    let (tx_link, tx_slot) = link_handoff();
+    let (to_line, from_terminal) = RingBuffer::new(LINE_QUEUE);
+    let tx_blocks = |sink: Box<dyn AudioSink>, tx_slot, from_terminal| {
+        let srate = sink.sample_rate();
+        let audio = TxAudio::new(
+            tx_slot,
+            from_terminal,
+            opt.standard,
+            srate,
+            max_block_len(srate),
+        );
+        TxTransport::Blocks(sink, Box::new(audio))
+    };
+    let tx: TxTransport = match (pcm_out, &opt.tx_wav) {
+        (Some(writer), _) => {
+            eprintln!(
+                "TX PCM: {}, format: {}, rate: {}",
+                opt.tx_pcm.as_ref().unwrap(),
+                opt.pcm_format,
+                opt.pcm_rate
+            );
+            tx_blocks(
+                Box::new(PcmOutput::new(writer, opt.pcm_format, opt.pcm_rate)),
+                tx_slot,
+                from_terminal,
+            )
+        }
+        (None, Some(path)) => {
+            let wav = match opt.tx_wav_g711 {
+                Some(law) => WavOutput::create_g711(path, opt.tx_wav_rate, law)?,
+                None => WavOutput::create(path, opt.tx_wav_rate, opt.tx_wav_float)?,
+            };
+            eprintln!("TX file: {}, rate: {}", path.display(), opt.tx_wav_rate);
+            tx_blocks(Box::new(wav), tx_slot, from_terminal)
+        }
+        // Na decodificação acelerada sem saída de TX, a transmissão é
+        // descartada; sem a ponta da callback, o que o terminal envia
+        // também é, em vez de encher o anel e travar o pty
+        (None, None) if opt.fast => {
+            drop((tx_slot, from_terminal));
+            TxTransport::Discard(opt.tx_wav_rate)
+        }
+        (None, None) => {
+            let (srate, stream) =
+                open_tx_device(&host, &opt.txdev, opt.standard, tx_slot, from_terminal)?;
+            TxTransport::Device(srate, stream)
+        }
+    };
+
+    let (rx_link, rx_slot) = link_handoff();
+    let carrier = Arc::new(AtomicBool::new(false));
+    let rx_blocks = |source: Box<dyn AudioSource>, rx_slot| {
+        let srate = source.sample_rate();
+        let audio = RxAudio::new(
+            rx_slot,
+            carrier.clone(),
+            opt.standard,
+            srate,
+            max_block_len(srate),
+        );
+        RxTransport::Blocks(source, Box::new(audio))
+    };
+    let rx: RxTransport = match (pcm_in, &opt.rx_wav) {
+        (Some(reader), _) => {
+            eprintln!(
+                "RX PCM: {}, format: {}, rate: {}",
+                opt.rx_pcm.as_ref().unwrap(),
+                opt.pcm_format,
+                opt.pcm_rate
+            );
+            rx_blocks(
+                Box::new(PcmInput::new(reader, opt.pcm_format, opt.pcm_rate)),
+                rx_slot,
+            )
+        }
+        (None, Some(path)) => {
+            let wav = WavInput::open(path)?;
+            eprintln!("RX file: {}, rate: {}", path.display(), wav.sample_rate());
+            rx_blocks(Box::new(wav), rx_slot)
+        }
+        (None, None) => {
+            let (srate, stream) =
+                open_rx_device(&host, &opt.rxdev, opt.standard, rx_slot, carrier.clone())?;
+            RxTransport::Device(srate, stream)
+        }
+    };
+
+    let tx_srate = modem_rate(opt.standard, tx.sample_rate());
+    let rx_srate = modem_rate(opt.standard, rx.sample_rate());
+    if tx_srate != tx.sample_rate() {
+        eprintln!(
+            "TX: resampling from {} to {} Hz",
+            tx_srate,
+            tx.sample_rate()
+        );
+    }
+    if rx_srate != rx.sample_rate() {
+        eprintln!(
+            "RX: resampling from {} to {} Hz",
+            rx.sample_rate(),
+            rx_srate
+        );
+    }
+    let mut blocks = BlockStreams {
+        rx: None,
+        tx: None,
+        fast: opt.fast,
+    };
+    let tx_stream = match tx {
+        TxTransport::Device(_, stream) => Some(stream),
+        TxTransport::Blocks(sink, audio) => {
+            blocks.tx = Some((sink, *audio));
+            None
+        }
+        TxTransport::Discard(_) => None,
+    };
+    let rx_stream = match rx {
+        RxTransport::Device(_, stream) => Some(stream),
+        RxTransport::Blocks(source, audio) => {
+            blocks.rx = Some((source, *audio));
+            None
+        }
+    };
+    let exit_at_end = opt.rx_pcm.is_some() || opt.tx_pcm.is_some();
+
+    let mut serial = start_modem(
+        opt,
+        tx_srate,
+        rx_srate,
+        LineEnds {
+            tx_link,
+            rx_link,
+            carrier,
+            to_line,
+        },
+        never(),
+    )?;
+
+    if let Some(stream) = &tx_stream {
+        stream.play()?;
+    }
+    if let Some(stream) = &rx_stream {
+        stream.play()?;
+    }
+    if blocks.rx.is_none() && blocks.tx.is_none() {
+        return serial.event_loop();
+    }
+    let blocks = std::thread::spawn(move || {
+        if let Err(err) = blocks.run() {
+            eprintln!("audio stream error: {}", err);
+        }
+    });
+    if !exit_at_end {
+        return serial.event_loop();
+    }
+
+    // Com PCM cru, o fim do fluxo de RX ou o fechamento do de TX pelo outro
+    // lado encerra o modem, como esperam os encadeamentos de programas
+    std::thread::spawn(move || {
+        if let Err(err) = serial.event_loop() {
+            eprintln!("serial error: {}", err);
+        }
+    });
+    let _ = blocks.join();
+    Ok(())
+}
+
+/// Pontas da ligação do lado de comandos: as trocas de metades com as
+/// callbacks de áudio, o DCD e o anel do que há a transmitir.
+struct LineEnds {
+    tx_link: LinkSender<TxLink>,
+    rx_link: LinkSender<RxLink>,
+    carrier: Arc<AtomicBool>,
+    to_line: Producer<u8>,
+}
+
+/// Cria o pty de um modem e, salvo no Bell 202 e na decodificação acelerada,
+/// o lado de comandos que o liga às metades da ligação; `from_ring` traz os
+/// toques da linha. Devolve o pty, cujo laço de eventos fica com quem chama.
+fn start_modem(
+    opt: Opt,
+    tx_srate: usize,
+    rx_srate: usize,
+    ends: LineEnds,
+    from_ring: Receiver<()>,
+) -> anyhow::Result<Serial> {
+    let LineEnds {
+        tx_link,
+        rx_link,
+        carrier,
+        to_line,
+    } = ends;
+    let (pty_to_modem, modem_from_pty) = unbounded();
+    let (modem_to_pty, pty_from_modem) = unbounded();
+    let serial = Serial::open(&opt.serdev, pty_from_modem, pty_to_modem)?;
+    // Filas de tamanho fixo, em que a callback de RX escreve sem alocar; os
+    // quadros do Bell 202 vêm da thread do HDLC, fora da callback
+    let (line_to_terminal, terminal_from_line) = bounded(LINE_QUEUE);
+    let (call_progress, controller_from_call_progress) = bounded(EVENT_QUEUE);
+    let (frames, terminal_from_frames) = unbounded();
+    let mut line = Line {
+        links: LinkBuilder {
+            params: link_params(&opt),
+            tx_srate,
+            rx_srate,
+            carrier,
+            rx_baud_rate: Arc::new(AtomicUsize::new(0)),
+            to_terminal: line_to_terminal,
+            frames,
+            call_progress,
+        },
+        tx_link,
+        rx_link,
+        rx_baud_rate: 0,
+        opt,
+    };
+
+    // No Bell 202 o pty fala KISS e os quadros vão por HDLC, sem comandos
+    // AT: a ligação fica estabelecida desde o início, como também na
+    // decodificação acelerada de arquivos, em que os prazos dos comandos não
+    // fariam sentido. Nos demais casos o interpretador Hayes fica entre o pty
+    // e a ligação.
+    if line.opt.standard == Standard::Bell202 {
+        line.off_hook(line.opt.answer, None);
+        let baud_rate = line.opt.standard.baud_rate(line.opt.answer);
+        forward_kiss_to_line(modem_from_pty, to_line, baud_rate);
+        std::thread::spawn(move || {
+            // As filas da ligação ficam abertas mesmo depois do fim do
+            // arquivo de RX, e o pty com elas
+            let _line = line;
+            for frame in terminal_from_frames {
+                match ax25::Frame::parse(&frame) {
+                    Ok(ax25_frame) => eprintln!("RX frame: {}", ax25_frame),
+                    Err(err) => eprintln!("RX frame: not AX.25 ({})", err),
+                }
+                for b in kiss::encode(0, &frame) {
+                    modem_to_pty.send(b).unwrap();
+                }
+            }
+        });
+    } else if line.opt.fast {
+        line.off_hook(line.opt.answer, None);
+        forward_to_line(modem_from_pty, to_line);
+        std::thread::spawn(move || {
+            let mut line = line;
+            for b in terminal_from_line {
+                line.report_baud_rate();
+                modem_to_pty.send(b).unwrap();
+            }
+        });
     } else {
-        host.input_devices()?
-            .find(|x| x.name().map(|y| y == opt.rxdev).unwrap_or(false))
+        let (hayes_to_line, line_from_hayes) = unbounded();
+        forward_to_line(line_from_hayes, to_line);
+        let controller = Controller {
+            hayes: Hayes::new(modem_to_pty.clone(), hayes_to_line),
+            line,
+            connection: Connection::new(),
+            rate: 0,
+            modem_control: serial.modem_control()?,
+            pending_dial: None,
+        };
+        std::thread::spawn(move || {
+            controller.run(
+                modem_from_pty,
+                modem_to_pty,
+                terminal_from_line,
+                controller_from_call_progress,
+                from_ring,
+            )
+        });
     }
-    .expect("failed to find RX device");
-    let rxcfg = rxdev.default_input_config().unwrap();
-    eprintln!("RX device: {}, config: {:?}", rxdev.name()?, rxcfg);
+    Ok(serial)
+}
+
+/// Passa os bytes do terminal ao anel lido pela callback de TX, esperando
+/// enquanto ele estiver cheio; sem transporte de TX, são descartados.
+fn forward_to_line(from_terminal: Receiver<u8>, mut to_line: Producer<u8>) {
+    std::thread::spawn(move || {
+        for b in from_terminal {
+            push_to_line(&mut to_line, b);
+        }
+    });
+}
+
+/// No Bell 202, monta os quadros KISS do terminal e passa ao anel da
+/// callback de TX os seus níveis de bit, já em HDLC.
+fn forward_kiss_to_line(from_terminal: Receiver<u8>, mut to_line: Producer<u8>, baud_rate: usize) {
+    std::thread::spawn(move || {
+        let mut framer = KissFramer::new(baud_rate);
+        for b in from_terminal {
+            framer.put_byte(b, |level| push_to_line(&mut to_line, level));
+        }
+    });
+}
+
+fn push_to_line(to_line: &mut Producer<u8>, value: u8) {
+    while to_line.push(value).is_err() && !to_line.is_abandoned() {
+        std::thread::sleep(BLOCK);
+    }
+}
+
+/// Modo loopback: um modem de origem e um de resposta, cada um com seu pty,
+/// ligados pela linha simulada em vez de dispositivos de áudio.
+fn loopback(opt: Opt) -> anyhow::Result<()> {
+    let srate = opt.loopback_rate;
+    let speriod = 1. / srate as f32;
+    let modem_srate = modem_rate(opt.standard, srate);
+    if modem_srate != srate {
+        eprintln!("line: resampling between {} and {} Hz", modem_srate, srate);
+    }
+    let params = LineParams {
+        gain: opt.line_gain,
+        noise: opt.line_noise,
+        delay: opt.line_delay / 1000.,
+        skew: opt.line_skew,
+    };
+    let (originate, originate_ends) = LoopbackEnd::new(opt.standard, srate);
+    let (answer, answer_ends) = LoopbackEnd::new(opt.standard, srate);
+    let (ring, answer_from_ring) = unbounded();
+
+    // O de origem disca se houver `--dial`; o de resposta atende os toques
+    // com ATA ou S0. No Bell 202, sem comandos, cada um fica num sentido.
+    eprintln!("originate modem:");
+    let mut originate_serial = start_modem(
+        Opt {
+            answer: false,
+            ..opt.clone()
+        },
+        modem_srate,
+        modem_srate,
+        originate_ends,
+        never(),
+    )?;
+    eprintln!("answer modem:");
+    let mut answer_serial = start_modem(
+        Opt {
+            answer: opt.standard == Standard::Bell202,
+            dial: None,
+            ..opt
+        },
+        modem_srate,
+        modem_srate,
+        answer_ends,
+        answer_from_ring,
+    )?;
+
+    let line = Loopback {
+        srate,
+        to_answer: LineSimulator::new(speriod, &params, 1),
+        to_originate: LineSimulator::new(speriod, &params, 2),
+        originate,
+        answer,
+        ring,
+    };
+    std::thread::spawn(move || line.run());
+    std::thread::spawn(move || {
+        if let Err(err) = answer_serial.event_loop() {
+            eprintln!("answer modem error: {}", err);
+        }
+    });
+    originate_serial.event_loop()
+}
 
-    let txdev = if opt.txdev == "default" {
+/// Abre o dispositivo de TX pelo nome e monta seu stream; devolve a taxa de
+/// amostragem do dispositivo e o stream, ainda parado.
+fn open_tx_device(
+    host: &cpal::Host,
+    name: &str,
+    standard: Standard,
+    tx_link: LinkSlot<TxLink>,
+    from_terminal: Consumer<u8>,
+) -> anyhow::Result<(usize, Stream)> {
+    let txdev = if name == "default" {
         host.default_output_device()
     } else {
         host.output_devices()?
-            .find(|x| x.name().map(|y| y == opt.txdev).unwrap_or(false))
+            .find(|x| x.name().map(|y| y == name).unwrap_or(false))
     }
     .expect("failed to find TX device");
     let txcfg = txdev.default_output_config().unwrap();
     eprintln!("TX device: {}, config: {:?}", txdev.name()?, txcfg);
-
-    let (tx_omega0, tx_omega1, rx_omega0, rx_omega1) = if opt.answer {
-        (
-            2. * PI * (1750. + 100.),
-            2. * PI * (1750. - 100.),
-            2. * PI * (1080. + 100.),
-            2. * PI * (1080. - 100.),
-        )
-    } else {
-        (
-            2. * PI * (1080. + 100.),
-            2. * PI * (1080. - 100.),
-            2. * PI * (1750. + 100.),
-            2. * PI * (1750. - 100.),
-        )
-    };
-
-    let tx_srate = txcfg.sample_rate().0 as usize;
-    assert!(
-        tx_srate % BAUD_RATE == 0,
-        "TX sampling rate {} is not a multiple of the baud rate {}",
-        tx_srate,
-        BAUD_RATE
-    );
-    let tx_samples_per_symbol = tx_srate / BAUD_RATE;
-    let tx_speriod = 1. / tx_srate as f32;
-
-    let (pty_to_uart_tx, uart_tx_from_pty) = unbounded();
-    let (uart_rx_to_pty, pty_from_uart_rx) = unbounded();
-    let mut serial = Serial::open(&opt.serdev, pty_from_uart_rx, pty_to_uart_tx)?;
-
-    let uart_tx = Arc::new(Mutex::new(UartTx::new(tx_samples_per_symbol)));
-    let v21_tx = V21TX::new(tx_speriod, tx_omega1, tx_omega0);
-
-    {
-        let uart_tx = uart_tx.clone();
-        std::thread::spawn(move || loop {
-            let b = uart_tx_from_pty.recv().unwrap();
-            uart_tx.lock().unwrap().put_byte(b);
-        });
-    }
+    let srate = txcfg.sample_rate().0 as usize;
+    let max_block = device_block(txcfg.buffer_size());
+    let audio = TxAudio::new(tx_link, from_terminal, standard, srate, max_block);
 
     let tx_stream = match txcfg.sample_format() {
-        cpal::SampleFormat::I8 => tx_run::<i8>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::I16 => tx_run::<i16>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::I32 => tx_run::<i32>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::I64 => tx_run::<i64>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::U8 => tx_run::<u8>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::U16 => tx_run::<u16>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::U32 => tx_run::<u32>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::U64 => tx_run::<u64>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::F32 => tx_run::<f32>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::F64 => tx_run::<f64>(&txdev, &txcfg.into(), uart_tx, v21_tx),
+        cpal::SampleFormat::I8 => tx_run::<i8>(&txdev, &txcfg.config(), audio, max_block),
+        cpal::SampleFormat::I16 => tx_run::<i16>(&txdev, &txcfg.config(), audio, max_block),
+        cpal::SampleFormat::I32 => tx_run::<i32>(&txdev, &txcfg.config(), audio, max_block),
+        cpal::SampleFormat::I64 => tx_run::<i64>(&txdev, &txcfg.config(), audio, max_block),
+        cpal::SampleFormat::U8 => tx_run::<u8>(&txdev, &txcfg.config(), audio, max_block),
+        cpal::SampleFormat::U16 => tx_run::<u16>(&txdev, &txcfg.config(), audio, max_block),
+        cpal::SampleFormat::U32 => tx_run::<u32>(&txdev, &txcfg.config(), audio, max_block),
+        cpal::SampleFormat::U64 => tx_run::<u64>(&txdev, &txcfg.config(), audio, max_block),
+        cpal::SampleFormat::F32 => tx_run::<f32>(&txdev, &txcfg.config(), audio, max_block),
+        cpal::SampleFormat::F64 => tx_run::<f64>(&txdev, &txcfg.config(), audio, max_block),
         sample_format => panic!("TX: Unsupported sample format '{sample_format}'"),
     }?;
+    Ok((srate, tx_stream))
+}
 
-    let rx_srate = rxcfg.sample_rate().0 as usize;
-    assert!(
-        rx_srate % BAUD_RATE == 0,
-        "RX sampling rate {} is not a multiple of the baud rate {}",
-        rx_srate,
-        BAUD_RATE
-    );
-    let rx_samples_per_symbol = rx_srate / BAUD_RATE;
-    let rx_speriod = 1. / rx_srate as f32;
+/// Abre o dispositivo de RX pelo nome e monta seu stream; devolve a taxa de
+/// amostragem do dispositivo e o stream, ainda parado.
+fn open_rx_device(
+    host: &cpal::Host,
+    name: &str,
+    standard: Standard,
+    rx_link: LinkSlot<RxLink>,
+    carrier: Arc<AtomicBool>,
+) -> anyhow::Result<(usize, Stream)> {
+    let rxdev = if name == "default" {
+        host.default_input_device()
+    } else {
+        host.input_devices()?
+            .find(|x| x.name().map(|y| y == name).unwrap_or(false))
+    }
+    .expect("failed to find RX device");
+    let rxcfg = rxdev.default_input_config().unwrap();
+    eprintln!("RX device: {}, config: {:?}", rxdev.name()?, rxcfg);
+    let srate = rxcfg.sample_rate().0 as usize;
+    let max_block = device_block(rxcfg.buffer_size());
+    let audio = RxAudio::new(rx_link, carrier, standard, srate, max_block);
 
-    let uart_rx = UartRx::new(rx_samples_per_symbol, uart_rx_to_pty);
-    let v21_rx = V21RX::new(rx_speriod, rx_samples_per_symbol, rx_omega1, rx_omega0);
     let rx_stream = match rxcfg.sample_format() {
-        cpal::SampleFormat::I8 => rx_run::<i8>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::I16 => rx_run::<i16>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::I32 => rx_run::<i32>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::I64 => rx_run::<i64>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::U8 => rx_run::<u8>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::U16 => rx_run::<u16>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::U32 => rx_run::<u32>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::U64 => rx_run::<u64>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::F32 => rx_run::<f32>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::F64 => rx_run::<f64>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
+        cpal::SampleFormat::I8 => rx_run::<i8>(&rxdev, &rxcfg.config(), audio, max_block),
+        cpal::SampleFormat::I16 => rx_run::<i16>(&rxdev, &rxcfg.config(), audio, max_block),
+        cpal::SampleFormat::I32 => rx_run::<i32>(&rxdev, &rxcfg.config(), audio, max_block),
+        cpal::SampleFormat::I64 => rx_run::<i64>(&rxdev, &rxcfg.config(), audio, max_block),
+        cpal::SampleFormat::U8 => rx_run::<u8>(&rxdev, &rxcfg.config(), audio, max_block),
+        cpal::SampleFormat::U16 => rx_run::<u16>(&rxdev, &rxcfg.config(), audio, max_block),
+        cpal::SampleFormat::U32 => rx_run::<u32>(&rxdev, &rxcfg.config(), audio, max_block),
+        cpal::SampleFormat::U64 => rx_run::<u64>(&rxdev, &rxcfg.config(), audio, max_block),
+        cpal::SampleFormat::F32 => rx_run::<f32>(&rxdev, &rxcfg.config(), audio, max_block),
+        cpal::SampleFormat::F64 => rx_run::<f64>(&rxdev, &rxcfg.config(), audio, max_block),
         sample_format => panic!("RX: Unsupported sample format '{sample_format}'"),
     }?;
+    Ok((srate, rx_stream))
+}
 
-    tx_stream.play()?;
-    rx_stream.play()?;
-    serial.event_loop()
+/// Lado de comandos do modem: o interpretador Hayes, a ligação e a máquina
+/// de estados da conexão dirigida pelo DCD.
+struct Controller {
+    hayes: Hayes,
+    line: Line,
+    connection: Connection,
+    rate: usize,
+    modem_control: ModemControl,
+    /// Discagem à espera do tom de discar, desde o instante dado
+    pending_dial: Option<(Instant, Dialer)>,
+}
+
+impl Controller {
+    /// Leva os bytes entre o pty, o interpretador de comandos e a ligação,
+    /// acompanha a portadora e os tons de progressão de chamada e executa as
+    /// ações pedidas pelo interpretador.
+    fn run(
+        mut self,
+        from_pty: Receiver<u8>,
+        to_pty: Sender<u8>,
+        from_line: Receiver<u8>,
+        from_call_progress: Receiver<CallProgressEvent>,
+        from_ring: Receiver<()>,
+    ) {
+        if self.line.opt.answer {
+            let action = self.hayes.answer(Instant::now());
+            self.execute(action);
+        } else if let Some(number) = self.line.opt.dial.clone() {
+            let action = self.hayes.dial(&number, Instant::now());
+            self.execute(action);
+        }
+        loop {
+            let action = select! {
+                recv(from_pty) -> b => self.hayes.put_byte(b.unwrap(), Instant::now()),
+                recv(from_line) -> b => {
+                    // Dados recebidos com a conexão suspensa por escape são
+                    // descartados
+                    if self.hayes.is_online() {
+                        to_pty.send(b.unwrap()).unwrap();
+                    }
+                    None
+                }
+                recv(from_call_progress) -> event => self.call_progress(event.unwrap()),
+                recv(from_ring) -> _ => self.hayes.ring(Instant::now()),
+                default(POLL_INTERVAL) => None,
+            };
+            self.line.report_baud_rate();
+            let now = Instant::now();
+            let action = action
+                .or_else(|| self.update_connection(now))
+                .or_else(|| self.check_dial_tone(now))
+                .or_else(|| self.hayes.poll(now));
+            if let Some(action) = action {
+                self.execute(action);
+            }
+        }
+    }
+
+    fn update_connection(&mut self, now: Instant) -> Option<Action> {
+        let carrier = self.line.links.carrier.load(Ordering::Relaxed);
+        match self.connection.update(carrier, now)? {
+            ConnectionEvent::Connect => {
+                self.modem_control.set_carrier(true);
+                self.hayes.connected(self.rate);
+                None
+            }
+            ConnectionEvent::NoCarrier => {
+                self.modem_control.set_carrier(false);
+                self.hayes.carrier_lost()
+            }
+        }
+    }
+
+    fn call_progress(&mut self, event: CallProgressEvent) -> Option<Action> {
+        match event {
+            CallProgressEvent::DialTone => {
+                let (_, dialer) = self.pending_dial.take()?;
+                self.line.dial(dialer);
+                None
+            }
+            CallProgressEvent::Ringback => {
+                self.hayes.ringback();
+                None
+            }
+            CallProgressEvent::Busy => self.hayes.call_failed(ResultCode::Busy),
+            // Número inexistente, mudado etc.
+            CallProgressEvent::SpecialInformation => self.hayes.call_failed(ResultCode::NoCarrier),
+        }
+    }
+
+    /// Desiste da discagem se o tom de discar não veio em S6 segundos.
+    fn check_dial_tone(&mut self, now: Instant) -> Option<Action> {
+        let (since, _) = self.pending_dial.as_ref()?;
+        let wait = Duration::from_secs(self.hayes.register(hayes::S_DIAL_TONE_WAIT) as u64);
+        if now.duration_since(*since) < wait {
+            return None;
+        }
+        self.pending_dial = None;
+        self.hayes.call_failed(ResultCode::NoDialtone)
+    }
+
+    fn execute(&mut self, action: Action) {
+        let (answer, dialer) = match action {
+            Action::Dial(number) => {
+                eprintln!("dialing: {}", number);
+                let params = DialParams {
+                    mode: self.line.opt.dial_mode,
+                    tone_duration: self.hayes.register(hayes::S_DTMF_DURATION) as f32 / 1000.,
+                    tone_gap: self.hayes.register(hayes::S_DTMF_DURATION) as f32 / 1000.,
+                    level: self.line.opt.dtmf_level,
+                    twist: self.line.opt.dtmf_twist,
+                    pause: self.hayes.register(hayes::S_COMMA_PAUSE) as f32,
+                    ..DialParams::default()
+                };
+                let tx_speriod = 1. / self.line.links.tx_srate as f32;
+                let dialer = Dialer::new(tx_speriod, &params, &number);
+                if self.line.opt.country.is_some() {
+                    // Só disca depois de ouvir o tom de discar
+                    self.pending_dial = Some((Instant::now(), dialer));
+                    (false, None)
+                } else {
+                    (false, Some(dialer))
+                }
+            }
+            Action::Answer => (true, None),
+            Action::Hangup => {
+                self.pending_dial = None;
+                self.line.on_hook();
+                self.connection.on_hook();
+                self.modem_control.set_carrier(false);
+                return;
+            }
+        };
+        self.rate = self.line.off_hook(answer, dialer);
+        let tenths = |register| Duration::from_millis(100 * self.hayes.register(register) as u64);
+        self.connection.off_hook(
+            Instant::now(),
+            tenths(hayes::S_CARRIER_DETECT),
+            tenths(hayes::S_CARRIER_LOSS),
+        );
+    }
+}
+
+/// Monta as metades da ligação no sentido pedido e as instala nas callbacks
+/// de áudio.
+struct Line {
+    opt: Opt,
+    links: LinkBuilder,
+    tx_link: LinkSender<TxLink>,
+    rx_link: LinkSender<RxLink>,
+    /// Última taxa de RX detectada já anunciada
+    rx_baud_rate: usize,
+}
+
+impl Line {
+    /// Sai do gancho no modo de resposta ou de origem, discando antes se
+    /// houver o que discar; devolve a taxa de recepção em bit/s.
+    fn off_hook(&mut self, answer: bool, dialer: Option<Dialer>) -> usize {
+        self.tx_link.set(Some(self.links.tx(answer, dialer)));
+        self.rx_link.set(Some(self.links.rx(answer)));
+        self.opt.standard.baud_rate(!answer)
+    }
+
+    /// Disca, já fora do gancho no modo de origem.
+    fn dial(&mut self, dialer: Dialer) {
+        self.tx_link.set(Some(self.links.tx(false, Some(dialer))));
+    }
+
+    fn on_hook(&mut self) {
+        self.tx_link.set(None);
+        self.rx_link.set(None);
+    }
+
+    /// Anuncia a taxa de RX que a callback acabou de detectar, se houver.
+    fn report_baud_rate(&mut self) {
+        let baud_rate = self.links.rx_baud_rate.load(Ordering::Relaxed);
+        if baud_rate != self.rx_baud_rate {
+            self.rx_baud_rate = baud_rate;
+            if baud_rate != 0 {
+                eprintln!("RX baud rate detected: {}", baud_rate);
+            }
+        }
+    }
+}
+
+/// Opções da linha de comando de que dependem as metades da ligação.
+fn link_params(opt: &Opt) -> LinkParams {
+    LinkParams {
+        standard: opt.standard,
+        framing: opt.framing,
+        majority_vote: opt.majority_vote,
+        autobaud: opt.autobaud,
+        carrier: CarrierParams {
+            on_threshold: opt.carrier_on,
+            off_threshold: opt.carrier_off,
+            min_level: opt.carrier_min_level,
+            ..CarrierParams::default()
+        },
+        guard_tone: opt.guard_tone,
+        answer_tone_reversals: opt.answer_tone_reversals,
+        country: opt.country,
+    }
 }
 
-pub fn tx_run<T>(
+/// Número de amostras do bloco dado, sem acumular erro de arredondamento em
+/// taxas como 11025 Hz.
+fn block_len(srate: usize, block: usize) -> usize {
+    let until = |block: usize| block * srate * BLOCK.as_millis() as usize / 1000;
+    until(block + 1) - until(block)
+}
+
+/// Maior número de amostras de um bloco à taxa dada.
+fn max_block_len(srate: usize) -> usize {
+    (srate * BLOCK.as_millis() as usize).div_ceil(1000)
+}
+
+/// Maior bloco que o dispositivo entrega às callbacks, limitado a
+/// `DEVICE_BLOCK`.
+fn device_block(buffer_size: &SupportedBufferSize) -> usize {
+    match buffer_size {
+        SupportedBufferSize::Range { max, .. } => (*max as usize).clamp(1, DEVICE_BLOCK),
+        SupportedBufferSize::Unknown => DEVICE_BLOCK,
+    }
+}
+
+/// Transporte das amostras de TX, com sua taxa de amostragem.
+enum TxTransport {
+    /// Dispositivo de áudio, que pede as amostras pela callback de `tx_run`
+    Device(usize, Stream),
+    /// Arquivo ou fluxo gravado em blocos por `BlockStreams`
+    Blocks(Box<dyn AudioSink>, Box<TxAudio>),
+    /// Transmissão descartada
+    Discard(usize),
+}
+
+impl TxTransport {
+    fn sample_rate(&self) -> usize {
+        match self {
+            TxTransport::Device(srate, _) | TxTransport::Discard(srate) => *srate,
+            TxTransport::Blocks(sink, _) => sink.sample_rate(),
+        }
+    }
+}
+
+/// Transporte das amostras de RX, com sua taxa de amostragem.
+enum RxTransport {
+    /// Dispositivo de áudio, que entrega as amostras à callback de `rx_run`
+    Device(usize, Stream),
+    /// Arquivo ou fluxo lido em blocos por `BlockStreams`
+    Blocks(Box<dyn AudioSource>, Box<RxAudio>),
+}
+
+impl RxTransport {
+    fn sample_rate(&self) -> usize {
+        match self {
+            RxTransport::Device(srate, _) => *srate,
+            RxTransport::Blocks(source, _) => source.sample_rate(),
+        }
+    }
+}
+
+/// Lados de áudio trocados em blocos com arquivos WAV ou fluxos de PCM cru
+/// em vez de dispositivos.
+struct BlockStreams {
+    rx: Option<(Box<dyn AudioSource>, RxAudio)>,
+    tx: Option<(Box<dyn AudioSink>, TxAudio)>,
+    fast: bool,
+}
+
+impl BlockStreams {
+    /// Troca blocos de 10 ms com os fluxos, em tempo real ou, no modo
+    /// acelerado, tão rápido quanto possível, até o fim do fluxo de RX ou o
+    /// fechamento do de TX pelo outro lado.
+    fn run(mut self) -> io::Result<()> {
+        let start = Instant::now();
+        for block in 0.. {
+            // TX antes de RX: dois modems ligados por fluxos esperariam um
+            // pelo outro se ambos começassem lendo
+            if let Some((tx, audio)) = self.tx.as_mut() {
+                let mut modem_out = vec![0.; block_len(tx.sample_rate(), block)];
+                audio.process(&mut modem_out);
+                match tx.write(&modem_out) {
+                    Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {
+                        eprintln!("TX: stream closed");
+                        break;
+                    }
+                    result => result?,
+                }
+                if block % 100 == 99 {
+                    tx.flush()?;
+                }
+            }
+            if let Some((rx, audio)) = self.rx.as_mut() {
+                let mut modem_in = vec![0.; block_len(rx.sample_rate(), block)];
+                let read = rx.read(&mut modem_in)?;
+                audio.process(&modem_in[..read]);
+                if read < modem_in.len() {
+                    eprintln!("RX: end of stream");
+                    break;
+                }
+            }
+            if !self.fast {
+                let deadline = start + BLOCK * (block as u32 + 1);
+                std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+            }
+        }
+        if let Some((tx, _)) = self.tx {
+            match tx.finish() {
+                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {}
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Metades da ligação de um dos modems do modo loopback.
+struct LoopbackEnd {
+    tx: TxAudio,
+    rx: RxAudio,
+}
+
+impl LoopbackEnd {
+    /// Monta as metades de um modem; devolve também as pontas que ficam com
+    /// o seu lado de comandos.
+    fn new(standard: Standard, srate: usize) -> (Self, LineEnds) {
+        let (tx_link, tx_slot) = link_handoff();
+        let (rx_link, rx_slot) = link_handoff();
+        let carrier = Arc::new(AtomicBool::new(false));
+        let (to_line, from_terminal) = RingBuffer::new(LINE_QUEUE);
+        // A linha simulada entrega ao receptor uma amostra além do bloco,
+        // quando o relógio dele está adiantado
+        let max_block = max_block_len(srate) + 1;
+        let end = Self {
+            tx: TxAudio::new(tx_slot, from_terminal, standard, srate, max_block),
+            rx: RxAudio::new(rx_slot, carrier.clone(), standard, srate, max_block),
+        };
+        let ends = LineEnds {
+            tx_link,
+            rx_link,
+            carrier,
+            to_line,
+        };
+        (end, ends)
+    }
+
+    fn is_off_hook(&self) -> bool {
+        self.tx.is_off_hook()
+    }
+}
+
+/// Linha simulada do modo loopback, que também faz tocar o modem de
+/// resposta enquanto o de origem chama.
+struct Loopback {
+    srate: usize,
+    originate: LoopbackEnd,
+    answer: LoopbackEnd,
+    to_answer: LineSimulator,
+    to_originate: LineSimulator,
+    ring: Sender<()>,
+}
+
+impl Loopback {
+    /// Troca blocos de 10 ms entre os modems, em tempo real. O relógio da
+    /// linha é o do modem de origem; o de resposta transmite tantas amostras
+    /// quantas recebe, já no seu relógio.
+    fn run(mut self) {
+        let ring_blocks = (RING_PERIOD.as_millis() / BLOCK.as_millis()) as usize;
+        let mut next_ring = None;
+        let mut answered = false;
+        let start = Instant::now();
+        for block in 0.. {
+            let mut originate_out = vec![0.; block_len(self.srate, block)];
+            self.originate.tx.process(&mut originate_out);
+            let answer_in = self.to_answer.process(&originate_out);
+            let mut answer_out = vec![0.; answer_in.len()];
+            self.answer.tx.process(&mut answer_out);
+            let originate_in = self.to_originate.process(&answer_out);
+            self.answer.rx.process(&answer_in);
+            self.originate.rx.process(&originate_in);
+
+            // Toca enquanto o de origem está fora do gancho e a chamada ainda
+            // não foi atendida
+            if !self.originate.is_off_hook() {
+                answered = false;
+                next_ring = None;
+            } else if self.answer.is_off_hook() {
+                answered = true;
+                next_ring = None;
+            } else if !answered && block >= *next_ring.get_or_insert(block) {
+                let _ = self.ring.send(());
+                next_ring = Some(block + ring_blocks);
+            }
+
+            let deadline = start + BLOCK * (block as u32 + 1);
+            std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        }
+    }
+}
+
+fn tx_run<T>(
     device: &cpal::Device,
     config: &cpal::StreamConfig,
-    uart_tx: Arc<Mutex<UartTx>>,
-    mut v21_tx: V21TX,
+    mut audio: TxAudio,
+    max_block: usize,
 ) -> Result<Stream, BuildStreamError>
 where
     T: SizedSample + FromSample<f32>,
@@ -163,19 +1060,20 @@ where
 
     let err_fn = |err| eprintln!("TX stream error: {}", err);
 
+    // Alocado aqui para o maior bloco; um bloco maior que ele é gerado aos
+    // pedaços
+    let mut modem_out = vec![0.; max_block];
     device.build_output_stream(
         config,
         move |audio_out: &mut [T], _: &cpal::OutputCallbackInfo| {
-            let bufsize = audio_out.len() / channels;
-            let mut uart_out = vec![1; bufsize];
-            uart_tx.lock().unwrap().get_samples(&mut uart_out);
-
-            let mut v21_out = vec![0.; bufsize];
-            v21_tx.modulate(&uart_out, &mut v21_out);
+            for audio_out in audio_out.chunks_mut(max_block * channels) {
+                let modem_out = &mut modem_out[..audio_out.len() / channels];
+                audio.process(modem_out);
 
-            for (frame, sample) in audio_out.chunks_mut(channels).zip(v21_out.iter()) {
-                for dest in frame.iter_mut() {
-                    *dest = T::from_sample(*sample);
+                for (frame, sample) in audio_out.chunks_mut(channels).zip(modem_out.iter()) {
+                    for dest in frame.iter_mut() {
+                        *dest = T::from_sample(*sample);
+                    }
                 }
             }
         },
@@ -184,11 +1082,11 @@ where
     )
 }
 
-pub fn rx_run<T>(
+fn rx_run<T>(
     device: &cpal::Device,
     config: &cpal::StreamConfig,
-    mut uart_rx: UartRx,
-    mut v21_rx: V21RX,
+    mut audio: RxAudio,
+    max_block: usize,
 ) -> Result<Stream, BuildStreamError>
 where
     T: SizedSample,
@@ -198,19 +1096,17 @@ where
 
     let err_fn = |err| eprintln!("RX stream error: {}", err);
 
+    let mut modem_in = vec![0.; max_block];
     device.build_input_stream(
         config,
         move |audio_in: &[T], _: &cpal::InputCallbackInfo| {
-            let bufsize = audio_in.len() / channels;
-            let mut v21_in = vec![0.; bufsize];
-            for (frame, dest) in audio_in.chunks(channels).zip(v21_in.iter_mut()) {
-                *dest = frame.first().unwrap().to_sample::<f32>();
+            for audio_in in audio_in.chunks(max_block * channels) {
+                let modem_in = &mut modem_in[..audio_in.len() / channels];
+                for (frame, dest) in audio_in.chunks(channels).zip(modem_in.iter_mut()) {
+                    *dest = frame.first().unwrap().to_sample::<f32>();
+                }
+                audio.process(modem_in);
             }
-
-            let mut uart_in = vec![1; bufsize];
-            v21_rx.demodulate(&v21_in, &mut uart_in);
-
-            uart_rx.put_samples(&uart_in);
         },
         err_fn,
         None,
@@ -0,0 +1,271 @@
+use crate::audio::{AudioSink, AudioSource};
+use crate::g711::Law;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Formato das amostras de PCM cru, sem cabeçalho, de um canal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PcmFormat {
+    /// Inteiros de 16 bits com sinal, little-endian
+    #[default]
+    S16Le,
+    /// Inteiros de 16 bits com sinal, big-endian
+    S16Be,
+    /// Inteiros de 8 bits sem sinal, centrados em 128
+    U8,
+    /// Ponto flutuante de 32 bits, little-endian
+    F32Le,
+    /// G.711, lei µ
+    ULaw,
+    /// G.711, lei A
+    ALaw,
+}
+
+impl PcmFormat {
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            PcmFormat::S16Le | PcmFormat::S16Be => 2,
+            PcmFormat::U8 | PcmFormat::ULaw | PcmFormat::ALaw => 1,
+            PcmFormat::F32Le => 4,
+        }
+    }
+
+    /// Converte os bytes de uma amostra num valor entre -1 e 1.
+    pub fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            PcmFormat::S16Le => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.,
+            PcmFormat::S16Be => i16::from_be_bytes([bytes[0], bytes[1]]) as f32 / 32768.,
+            PcmFormat::U8 => (bytes[0] as f32 - 128.) / 128.,
+            PcmFormat::F32Le => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            PcmFormat::ULaw => Law::ULaw.decode(bytes[0]),
+            PcmFormat::ALaw => Law::ALaw.decode(bytes[0]),
+        }
+    }
+
+    /// Acrescenta a `bytes` a amostra dada, limitada ao intervalo de -1 a 1.
+    /// A escala é a mesma da leitura, saturando em +1 nos formatos inteiros.
+    pub fn encode(self, sample: f32, bytes: &mut Vec<u8>) {
+        let sample = sample.clamp(-1., 1.);
+        let s16 = || (sample * 32768.).round().min(i16::MAX as f32) as i16;
+        match self {
+            PcmFormat::S16Le => bytes.extend(s16().to_le_bytes()),
+            PcmFormat::S16Be => bytes.extend(s16().to_be_bytes()),
+            PcmFormat::U8 => bytes.push((sample * 128. + 128.).round().min(255.) as u8),
+            PcmFormat::F32Le => bytes.extend(sample.to_le_bytes()),
+            PcmFormat::ULaw => bytes.push(Law::ULaw.encode(sample)),
+            PcmFormat::ALaw => bytes.push(Law::ALaw.encode(sample)),
+        }
+    }
+}
+
+impl FromStr for PcmFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "s16le" | "s16" => Ok(PcmFormat::S16Le),
+            "s16be" => Ok(PcmFormat::S16Be),
+            "u8" => Ok(PcmFormat::U8),
+            "f32le" | "f32" => Ok(PcmFormat::F32Le),
+            "ulaw" | "mulaw" => Ok(PcmFormat::ULaw),
+            "alaw" => Ok(PcmFormat::ALaw),
+            _ => Err(format!(
+                "invalid PCM format '{}' (expected s16le, s16be, u8, f32le, ulaw or alaw)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for PcmFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PcmFormat::S16Le => write!(f, "s16le"),
+            PcmFormat::S16Be => write!(f, "s16be"),
+            PcmFormat::U8 => write!(f, "u8"),
+            PcmFormat::F32Le => write!(f, "f32le"),
+            PcmFormat::ULaw => write!(f, "ulaw"),
+            PcmFormat::ALaw => write!(f, "alaw"),
+        }
+    }
+}
+
+/// Ponta de um fluxo de PCM cru: `-` para a entrada ou saída padrão,
+/// `unix:CAMINHO` para um socket UNIX de fluxo já à escuta e qualquer outro
+/// valor para um arquivo ou FIFO.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PcmEndpoint {
+    Stdio,
+    Unix(PathBuf),
+    File(PathBuf),
+}
+
+impl FromStr for PcmEndpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "-" {
+            Ok(PcmEndpoint::Stdio)
+        } else if let Some(path) = s.strip_prefix("unix:") {
+            Ok(PcmEndpoint::Unix(path.into()))
+        } else {
+            Ok(PcmEndpoint::File(s.into()))
+        }
+    }
+}
+
+impl std::fmt::Display for PcmEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PcmEndpoint::Stdio => write!(f, "-"),
+            PcmEndpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+            PcmEndpoint::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+type PcmReader = Box<dyn Read + Send>;
+type PcmWriter = Box<dyn Write + Send>;
+
+/// Abre as pontas de RX e de TX pedidas. Se as duas forem o mesmo socket
+/// UNIX, uma só conexão leva os dois sentidos.
+pub fn connect(
+    rx: Option<&PcmEndpoint>,
+    tx: Option<&PcmEndpoint>,
+) -> io::Result<(Option<PcmReader>, Option<PcmWriter>)> {
+    #[cfg(unix)]
+    if let (Some(PcmEndpoint::Unix(rx_path)), Some(PcmEndpoint::Unix(tx_path))) = (rx, tx) {
+        if rx_path == tx_path {
+            let stream = std::os::unix::net::UnixStream::connect(rx_path)?;
+            return Ok((Some(Box::new(stream.try_clone()?)), Some(Box::new(stream))));
+        }
+    }
+
+    let reader = rx
+        .map(|endpoint| -> io::Result<PcmReader> {
+            match endpoint {
+                PcmEndpoint::Stdio => Ok(Box::new(io::stdin())),
+                PcmEndpoint::Unix(path) => Ok(Box::new(connect_unix(path)?)),
+                PcmEndpoint::File(path) => Ok(Box::new(File::open(path)?)),
+            }
+        })
+        .transpose()?;
+    let writer = tx
+        .map(|endpoint| -> io::Result<PcmWriter> {
+            match endpoint {
+                PcmEndpoint::Stdio => Ok(Box::new(io::stdout())),
+                PcmEndpoint::Unix(path) => Ok(Box::new(connect_unix(path)?)),
+                PcmEndpoint::File(path) => Ok(Box::new(File::create(path)?)),
+            }
+        })
+        .transpose()?;
+    Ok((reader, writer))
+}
+
+#[cfg(unix)]
+fn connect_unix(path: &Path) -> io::Result<std::os::unix::net::UnixStream> {
+    std::os::unix::net::UnixStream::connect(path)
+}
+
+#[cfg(not(unix))]
+fn connect_unix(_path: &Path) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "UNIX sockets are not supported on this platform",
+    ))
+}
+
+/// Amostras lidas de um fluxo de PCM cru.
+pub struct PcmInput<R> {
+    reader: R,
+    format: PcmFormat,
+    sample_rate: usize,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> PcmInput<R> {
+    pub fn new(reader: R, format: PcmFormat, sample_rate: usize) -> Self {
+        Self {
+            reader,
+            format,
+            sample_rate,
+            buffer: vec![],
+        }
+    }
+}
+
+impl<R: Read + Send> AudioSource for PcmInput<R> {
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    /// Espera até ter todas as amostras pedidas ou o fim do fluxo; bytes de
+    /// uma amostra incompleta no fim são descartados.
+    fn read(&mut self, samples: &mut [f32]) -> io::Result<usize> {
+        let bytes_per_sample = self.format.bytes_per_sample();
+        self.buffer.resize(samples.len() * bytes_per_sample, 0);
+        let mut filled = 0;
+        while filled < self.buffer.len() {
+            match self.reader.read(&mut self.buffer[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let read = filled / bytes_per_sample;
+        for (sample, bytes) in samples
+            .iter_mut()
+            .zip(self.buffer[..read * bytes_per_sample].chunks(bytes_per_sample))
+        {
+            *sample = self.format.decode(bytes);
+        }
+        Ok(read)
+    }
+}
+
+/// Amostras gravadas num fluxo de PCM cru.
+pub struct PcmOutput<W> {
+    writer: W,
+    format: PcmFormat,
+    sample_rate: usize,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> PcmOutput<W> {
+    pub fn new(writer: W, format: PcmFormat, sample_rate: usize) -> Self {
+        Self {
+            writer,
+            format,
+            sample_rate,
+            buffer: vec![],
+        }
+    }
+}
+
+impl<W: Write + Send> AudioSink for PcmOutput<W> {
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        self.buffer.clear();
+        for &sample in samples {
+            self.format.encode(sample, &mut self.buffer);
+        }
+        // Quem lê PCM cru costuma tocá-lo em tempo real, então nada fica
+        // retido em buffer entre os blocos
+        self.writer.write_all(&self.buffer)?;
+        self.writer.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,146 @@
+use std::f64::consts::PI;
+
+/// Passagens por zero do sinc de cada lado do centro do filtro.
+const ZERO_CROSSINGS: usize = 12;
+
+/// Pontos da tabela do filtro por passagem por zero; os valores entre eles
+/// são interpolados linearmente.
+const TABLE_RESOLUTION: usize = 256;
+
+/// Fração da menor das duas frequências de Nyquist mantida pelo filtro
+/// antialiasing; a faixa de transição fica acima dela.
+const PASSBAND: f64 = 0.9;
+
+/// Conversor de taxa de amostragem por interpolação de banda limitada: cada
+/// amostra de saída é a soma das de entrada ponderadas por um sinc com
+/// janela de Kaiser, centrado no instante da saída e com a frequência de
+/// corte abaixo da menor das duas taxas, de modo que sirva tanto para subir
+/// quanto para descer a taxa, em qualquer razão.
+pub struct Resampler {
+    /// Amostras de entrada por amostra de saída
+    step: f64,
+    /// Frequência de corte relativa à taxa de Nyquist da entrada
+    cutoff: f64,
+    /// Meia largura do filtro, em amostras de entrada
+    half_width: usize,
+    table: Vec<f32>,
+
+    // Atributos auxiliares
+    history: Vec<f32>,
+    position: f64,
+}
+
+impl Resampler {
+    pub fn new(input_rate: usize, output_rate: usize) -> Self {
+        let step = input_rate as f64 / output_rate as f64;
+        let cutoff = PASSBAND * (output_rate as f64 / input_rate as f64).min(1.);
+        let half_width = (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize;
+
+        // Sinc com janela de Kaiser (beta = 8, rejeição perto de 80 dB),
+        // tabelado de 0 a ZERO_CROSSINGS passagens por zero
+        let beta = 8.;
+        let table = (0..=ZERO_CROSSINGS * TABLE_RESOLUTION + 1)
+            .map(|i| {
+                let x = i as f64 / TABLE_RESOLUTION as f64;
+                let sinc = if x == 0. {
+                    1.
+                } else {
+                    (PI * x).sin() / (PI * x)
+                };
+                let r = (x / ZERO_CROSSINGS as f64).min(1.);
+                let window = bessel_i0(beta * (1. - r * r).sqrt()) / bessel_i0(beta);
+                (sinc * window) as f32
+            })
+            .collect();
+
+        Self {
+            step,
+            cutoff,
+            half_width,
+            table,
+            // O filtro começa centrado na primeira amostra de entrada, com
+            // silêncio antes dela
+            history: vec![0.; half_width],
+            position: half_width as f64,
+        }
+    }
+
+    /// Amostras de entrada consumidas por amostra de saída.
+    pub fn ratio(&self) -> f64 {
+        self.step
+    }
+
+    /// Atraso introduzido pelo filtro, em amostras de entrada.
+    pub fn delay(&self) -> usize {
+        self.half_width
+    }
+
+    /// Maior número de amostras que `process` acrescenta à saída para uma
+    /// entrada do tamanho dado.
+    pub fn max_output(&self, input_len: usize) -> usize {
+        (input_len as f64 / self.step).ceil() as usize + 1
+    }
+
+    /// Reserva memória para entradas de até `input_len` amostras, de modo
+    /// que `process` não aloque. Entre as chamadas sobram no histórico menos
+    /// de duas meias larguras do filtro.
+    pub fn reserve(&mut self, input_len: usize) {
+        let capacity = 2 * self.half_width + 1 + input_len;
+        self.history
+            .reserve(capacity.saturating_sub(self.history.len()));
+    }
+
+    /// Converte as amostras de entrada e acrescenta a `output` as de saída
+    /// que já podem ser calculadas; as demais saem nas chamadas seguintes.
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        self.history.extend_from_slice(input);
+        let half_width = self.half_width as f64;
+        while self.position + half_width < self.history.len() as f64 {
+            output.push(self.interpolate(self.position));
+            self.position += self.step;
+        }
+
+        // Descarta as amostras que nenhuma saída futura alcança
+        let consumed = (self.position - half_width).floor().max(0.) as usize;
+        self.history.drain(..consumed);
+        self.position -= consumed as f64;
+    }
+
+    fn interpolate(&self, position: f64) -> f32 {
+        let first = (position - self.half_width as f64).ceil().max(0.) as usize;
+        let last =
+            ((position + self.half_width as f64).floor() as usize).min(self.history.len() - 1);
+        let mut sum = 0.;
+        for (n, &sample) in self.history[first..=last].iter().enumerate() {
+            let distance = ((first + n) as f64 - position).abs() * self.cutoff;
+            sum += sample * self.kernel(distance);
+        }
+        sum * self.cutoff as f32
+    }
+
+    /// Filtro na distância dada, em passagens por zero.
+    fn kernel(&self, distance: f64) -> f32 {
+        let index = distance * TABLE_RESOLUTION as f64;
+        let i = index as usize;
+        if i >= ZERO_CROSSINGS * TABLE_RESOLUTION {
+            return 0.;
+        }
+        let frac = (index - i as f64) as f32;
+        self.table[i] * (1. - frac) + self.table[i + 1] * frac
+    }
+}
+
+/// Função de Bessel modificada de primeira espécie e ordem zero, pela série
+/// de potências.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.;
+    let mut term = 1.;
+    for k in 1..50 {
+        term *= (x / (2. * k as f64)).powi(2);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
@@ -1,5 +1,7 @@
+#[allow(clippy::single_component_path_imports)]
 use anyhow;
 use crossbeam_channel::{Receiver, Sender};
+#[allow(clippy::single_component_path_imports)]
 use nix;
 use std::os::fd::{AsRawFd, OwnedFd};
 
@@ -36,6 +38,14 @@ impl Serial {
         Ok(Self { to_uart, pty })
     }
 
+    /// Linhas de controle do modem da pty, para uso em outra thread.
+    pub fn modem_control(&self) -> anyhow::Result<ModemControl> {
+        Ok(ModemControl {
+            pty: self.pty.try_clone()?,
+            supported: true,
+        })
+    }
+
     pub fn event_loop(&mut self) -> anyhow::Result<()> {
         loop {
             let mut buf: [u8; 1] = [0];
@@ -57,3 +67,34 @@ impl Serial {
         }
     }
 }
+
+/// Linhas de controle do modem vistas pelo terminal.
+pub struct ModemControl {
+    pty: OwnedFd,
+    supported: bool,
+}
+
+impl ModemControl {
+    /// Liga ou desliga o DCD. A pty do Linux não costuma implementar as
+    /// linhas de controle; nesse caso o aviso sai uma vez e a conexão fica
+    /// sinalizada só pelas mensagens CONNECT/NO CARRIER.
+    pub fn set_carrier(&mut self, on: bool) {
+        if !self.supported {
+            return;
+        }
+        let request = if on {
+            nix::libc::TIOCMBIS
+        } else {
+            nix::libc::TIOCMBIC
+        };
+        let bits: nix::libc::c_int = nix::libc::TIOCM_CAR;
+        let res = unsafe { nix::libc::ioctl(self.pty.as_raw_fd(), request, &bits) };
+        if res < 0 {
+            eprintln!(
+                "serial port has no modem control lines: {}",
+                nix::errno::Errno::last()
+            );
+            self.supported = false;
+        }
+    }
+}
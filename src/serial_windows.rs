@@ -158,6 +158,11 @@ impl Serial {
         }
     }
 
+    /// Linhas de controle do modem do porto, para uso em outra thread.
+    pub fn modem_control(&self) -> anyhow::Result<ModemControl> {
+        Ok(ModemControl)
+    }
+
     pub fn event_loop(&mut self) -> anyhow::Result<()> {
         unsafe {
             let mut dw_read: DWORD = 0;
@@ -224,3 +229,11 @@ impl Serial {
         }
     }
 }
+
+/// Linhas de controle do modem vistas pelo terminal. Ainda não sinalizadas
+/// no Windows: a conexão é indicada só pelas mensagens CONNECT/NO CARRIER.
+pub struct ModemControl;
+
+impl ModemControl {
+    pub fn set_carrier(&mut self, _on: bool) {}
+}
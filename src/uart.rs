@@ -1,15 +1,305 @@
-use crossbeam_channel::Sender;
+use crossbeam_channel::{Sender, TrySendError};
 use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+    Mark,
+    Space,
+}
+
+impl Parity {
+    /// Bit de paridade a transmitir junto de `data`, ou `None` se não há paridade.
+    pub fn bit(self, data: u8) -> Option<u8> {
+        let ones = data.count_ones() as u8;
+        match self {
+            Parity::None => None,
+            Parity::Even => Some(ones & 1),
+            Parity::Odd => Some(!ones & 1),
+            Parity::Mark => Some(1),
+            Parity::Space => Some(0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    OnePointFive,
+    Two,
+}
+
+impl StopBits {
+    /// Duração do(s) bit(s) de parada em amostras.
+    pub fn samples(self, samples_per_symbol: usize) -> usize {
+        match self {
+            StopBits::One => samples_per_symbol,
+            StopBits::OnePointFive => samples_per_symbol * 3 / 2,
+            StopBits::Two => 2 * samples_per_symbol,
+        }
+    }
+}
+
+/// Formato de um caractere na linha: bits de dados, paridade e bits de parada
+/// (o bit de partida é sempre um só). O padrão é 8N1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Framing {
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl Framing {
+    pub fn new(data_bits: u8, parity: Parity, stop_bits: StopBits) -> Self {
+        assert!(
+            (5..=8).contains(&data_bits),
+            "UART: {} data bits not supported (must be 5 to 8)",
+            data_bits
+        );
+        Self {
+            data_bits,
+            parity,
+            stop_bits,
+        }
+    }
+
+    pub fn data_mask(&self) -> u8 {
+        (0xffu16 >> (8 - self.data_bits)) as u8
+    }
+
+    /// Bits entre o bit de partida e o bit de parada (dados + paridade).
+    pub fn payload_bits(&self) -> usize {
+        self.data_bits as usize + if self.parity == Parity::None { 0 } else { 1 }
+    }
+
+    /// Duração de um caractere completo em amostras.
+    pub fn samples_per_char(&self, samples_per_symbol: usize) -> usize {
+        (1 + self.payload_bits()) * samples_per_symbol + self.stop_bits.samples(samples_per_symbol)
+    }
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Self::new(8, Parity::None, StopBits::One)
+    }
+}
+
+impl fmt::Display for Framing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Even => 'E',
+            Parity::Odd => 'O',
+            Parity::Mark => 'M',
+            Parity::Space => 'S',
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => "1",
+            StopBits::OnePointFive => "1.5",
+            StopBits::Two => "2",
+        };
+        write!(f, "{}{}{}", self.data_bits, parity, stop_bits)
+    }
+}
+
+impl FromStr for Framing {
+    type Err = String;
+
+    /// Aceita a notação usual, como "8N1", "7E1", "7O2" ou "5N1.5".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid framing '{}' (expected e.g. 8N1, 7E1, 7O2)", s);
+        let mut chars = s.chars();
+
+        let data_bits = chars
+            .next()
+            .and_then(|c| c.to_digit(10))
+            .filter(|d| (5..=8).contains(d))
+            .ok_or_else(err)? as u8;
+
+        let parity = match chars.next().map(|c| c.to_ascii_uppercase()) {
+            Some('N') => Parity::None,
+            Some('E') => Parity::Even,
+            Some('O') => Parity::Odd,
+            Some('M') => Parity::Mark,
+            Some('S') => Parity::Space,
+            _ => return Err(err()),
+        };
+
+        let stop_bits = match chars.as_str() {
+            "1" => StopBits::One,
+            "1.5" => StopBits::OnePointFive,
+            "2" => StopBits::Two,
+            _ => return Err(err()),
+        };
+
+        Ok(Self::new(data_bits, parity, stop_bits))
+    }
+}
+
+/// Eventos entregues pelo receptor. Os erros de enquadramento e de paridade
+/// carregam o dado recebido mesmo assim.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RxEvent {
+    Byte(u8),
+    FramingError(u8),
+    ParityError(u8),
+    Break,
+}
+
+/// Contadores acumulados pelo receptor desde sua criação.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RxStats {
+    /// Bytes sem erro efetivamente entregues; os perdidos por overrun não
+    /// entram aqui.
+    pub bytes: usize,
+    pub framing_errors: usize,
+    pub parity_errors: usize,
+    pub breaks: usize,
+    /// Eventos descartados por encontrar o canal cheio. Só um canal limitado
+    /// (`bounded`) enche; com um `unbounded` este contador fica sempre em zero.
+    pub overruns: usize,
+}
+
+/// Como o receptor decide o valor de cada bit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SamplingMode {
+    /// Uma única amostra no meio do bit, contada a partir do bit de partida.
+    #[default]
+    MidBit,
+    /// Votação por maioria na metade central do bit, com recentragem a cada
+    /// transição observada dentro do caractere.
+    MajorityVote,
+}
+
+/// Taxas que a detecção automática consegue travar.
+pub const STANDARD_BAUD_RATES: [usize; 5] = [110, 150, 300, 600, 1200];
+
+/// Mede a duração dos pulsos entre transições e escolhe a menor taxa padrão
+/// da qual quase todos eles são múltiplos inteiros. Taxas maiores também
+/// explicariam os pulsos (um pulso de 2 bits a 600 baud tem a largura de um
+/// de 4 bits a 1200), por isso a busca começa pela menor.
+///
+/// As amostras e as transições dos últimos `MAX_PENDING_SECONDS` ficam em
+/// anéis de capacidade fixa, alocados na criação: o detector roda na
+/// callback de áudio.
+struct BaudDetector {
+    sample_rate: usize,
+    pending: VecDeque<u8>,
+    level: u8,
+    sample_count: usize,
+    edges: VecDeque<usize>,
+}
+
+impl BaudDetector {
+    const MIN_PULSES: usize = 24;
+    const MAX_PENDING_SECONDS: usize = 2;
+
+    fn new(sample_rate: usize) -> Self {
+        let max_pending = Self::MAX_PENDING_SECONDS * sample_rate;
+        Self {
+            sample_rate,
+            pending: VecDeque::with_capacity(max_pending),
+            level: 1,
+            sample_count: 0,
+            // Uma transição por amostra, no pior caso, mais a que acaba de
+            // chegar
+            edges: VecDeque::with_capacity(max_pending + 1),
+        }
+    }
+
+    /// Acumula as amostras e devolve a taxa detectada quando houver pulsos
+    /// suficientes para decidir.
+    fn put_samples(&mut self, buffer: &[u8]) -> Option<usize> {
+        let max_pending = Self::MAX_PENDING_SECONDS * self.sample_rate;
+        for &sample in buffer {
+            if sample != self.level {
+                self.level = sample;
+                self.edges.push_back(self.sample_count);
+            }
+            // Só guarda a partir da primeira transição, e no máximo alguns
+            // segundos, para não crescer sem limite numa linha sem dados
+            if !self.edges.is_empty() {
+                if self.pending.len() == max_pending {
+                    self.pending.pop_front();
+                }
+                self.pending.push_back(sample);
+            }
+            self.sample_count += 1;
+
+            let oldest = self.sample_count.saturating_sub(max_pending);
+            while self.edges.front().is_some_and(|&edge| edge < oldest) {
+                self.edges.pop_front();
+            }
+        }
+
+        STANDARD_BAUD_RATES
+            .iter()
+            .copied()
+            .find(|&baud_rate| self.fits(baud_rate))
+    }
+
+    fn fits(&self, baud_rate: usize) -> bool {
+        let bit_width = self.sample_rate as f32 / baud_rate as f32;
+
+        let (mut total, mut good) = (0, 0);
+        let mut last_edge: Option<usize> = None;
+        for &edge in &self.edges {
+            // Transições a menos de meio bit da anterior são ruído logo após
+            // uma transição verdadeira e não delimitam pulso algum
+            let width = match last_edge {
+                Some(last) => (edge - last) as f32 / bit_width,
+                None => f32::INFINITY,
+            };
+            if width < 0.5 {
+                continue;
+            }
+            last_edge = Some(edge);
+
+            // pulsos muito longos são linha ociosa entre caracteres
+            if width <= 12. {
+                total += 1;
+                if (width - width.round()).abs() <= 0.25 {
+                    good += 1;
+                }
+            }
+        }
+        total >= Self::MIN_PULSES && good * 10 >= total * 9
+    }
+}
+
+enum RxSink {
+    Bytes(Sender<u8>),
+    Events(Sender<RxEvent>),
+}
 
 pub struct UartRx {
     samples_per_symbol: usize,
-    to_pty: Sender<u8>,
+    framing: Framing,
+    sink: RxSink,
+    stats: RxStats,
+    detect_window: usize,
+    detect_threshold: usize,
+    mid_bit_delay: usize,
     history: VecDeque<u8>,
     state: RxState,
     sample_count: usize,
     bit_index: usize,
     current_byte: u8,
+    parity_sample: u8,
     mid_bit_counter: usize,
+    high_count: usize,
+    sampling_mode: SamplingMode,
+    bit_position: f32,
+    votes_one: usize,
+    votes_total: usize,
+    last_edge: usize,
+    previous_sample: u8,
+    autobaud: Option<BaudDetector>,
+    baud_rate: Option<usize>,
 }
 
 enum RxState {
@@ -17,46 +307,294 @@ enum RxState {
     Receiving,
     StopBit,
     MidBit,
+    Voting,
+    Break,
 }
 
 impl UartRx {
-    pub fn new(samples_per_symbol: usize, to_pty: Sender<u8>) -> Self {
-        Self {
+    /// Receptor que entrega apenas os bytes de dados, inclusive os recebidos
+    /// com erro de enquadramento ou paridade. Condições de break são descartadas.
+    pub fn new(samples_per_symbol: usize, framing: Framing, to_pty: Sender<u8>) -> Self {
+        Self::with_sink(samples_per_symbol, framing, RxSink::Bytes(to_pty))
+    }
+
+    /// Receptor que entrega todos os eventos, inclusive erros e breaks.
+    pub fn with_events(
+        samples_per_symbol: usize,
+        framing: Framing,
+        events: Sender<RxEvent>,
+    ) -> Self {
+        Self::with_sink(samples_per_symbol, framing, RxSink::Events(events))
+    }
+
+    fn with_sink(samples_per_symbol: usize, framing: Framing, sink: RxSink) -> Self {
+        let mut uart_rx = Self {
             samples_per_symbol,
-            to_pty,
-            history: VecDeque::with_capacity(30),
+            framing,
+            sink,
+            stats: RxStats::default(),
+            detect_window: 0,
+            detect_threshold: 0,
+            mid_bit_delay: 0,
+            history: VecDeque::new(),
             state: RxState::Idle,
             mid_bit_counter: 0,
             sample_count: 0,
             bit_index: 0,
             current_byte: 0,
+            parity_sample: 0,
+            high_count: 0,
+            sampling_mode: SamplingMode::default(),
+            bit_position: 0.,
+            votes_one: 0,
+            votes_total: 0,
+            last_edge: 0,
+            previous_sample: 0,
+            autobaud: None,
+            baud_rate: None,
+        };
+        uart_rx.set_samples_per_symbol(samples_per_symbol);
+        uart_rx
+    }
+
+    fn set_samples_per_symbol(&mut self, samples_per_symbol: usize) {
+        // O bit de partida é reconhecido quando a maior parte de uma janela de
+        // 3/16 de símbolo está em 0; daí espera-se até o meio do símbolo.
+        // A 48 kHz e 300 baud isso dá janela de 30, limiar de 25 e espera de 50.
+        self.samples_per_symbol = samples_per_symbol;
+        self.detect_window = (samples_per_symbol * 3 / 16).max(3);
+        self.detect_threshold = self.detect_window * 5 / 6;
+        self.mid_bit_delay = (samples_per_symbol / 2).saturating_sub(self.detect_window);
+        // O histórico chega a uma amostra além da janela antes de descartar
+        // a mais antiga; `reserve` só aloca se a capacidade não bastar
+        self.history.clear();
+        self.history.reserve(self.detect_window + 1);
+    }
+
+    /// Passa a detectar a taxa de transmissão a partir da largura dos pulsos
+    /// recebidos, ignorando o `samples_per_symbol` dado na construção. Nada é
+    /// decodificado até a taxa ser travada; então as amostras acumuladas são
+    /// processadas normalmente. A memória da detecção e a do histórico na
+    /// menor taxa padrão são alocadas aqui, e não quando a taxa trava.
+    pub fn set_autobaud(&mut self, sample_rate: usize) {
+        self.autobaud = Some(BaudDetector::new(sample_rate));
+        self.baud_rate = None;
+        let slowest = sample_rate / STANDARD_BAUD_RATES[0];
+        self.history.reserve((slowest * 3 / 16).max(3) + 1);
+    }
+
+    /// Taxa escolhida pela detecção automática, se já travou.
+    pub fn baud_rate(&self) -> Option<usize> {
+        self.baud_rate
+    }
+
+    pub fn set_sampling_mode(&mut self, sampling_mode: SamplingMode) {
+        self.sampling_mode = sampling_mode;
+    }
+
+    pub fn stats(&self) -> RxStats {
+        self.stats
+    }
+
+    fn emit(&mut self, event: RxEvent) {
+        match event {
+            RxEvent::Byte(_) => {}
+            RxEvent::FramingError(_) => self.stats.framing_errors += 1,
+            RxEvent::ParityError(_) => self.stats.parity_errors += 1,
+            RxEvent::Break => self.stats.breaks += 1,
+        }
+
+        let full = match (&self.sink, event) {
+            (RxSink::Events(sender), event) => {
+                matches!(sender.try_send(event), Err(TrySendError::Full(_)))
+            }
+            (RxSink::Bytes(_), RxEvent::Break) => false,
+            (
+                RxSink::Bytes(sender),
+                RxEvent::Byte(b) | RxEvent::FramingError(b) | RxEvent::ParityError(b),
+            ) => matches!(sender.try_send(b), Err(TrySendError::Full(_))),
+        };
+
+        if full {
+            self.stats.overruns += 1;
+        } else if let RxEvent::Byte(_) = event {
+            self.stats.bytes += 1;
+        }
+    }
+
+    fn start_char(&mut self) {
+        self.bit_index = 0;
+        self.sample_count = 0;
+        self.current_byte = 0;
+        self.parity_sample = 0;
+    }
+
+    /// Encerra o caractere com o valor amostrado do bit de parada.
+    fn end_of_char(&mut self, stop_bit: u8) {
+        let byte = self.current_byte;
+        if stop_bit == 0 {
+            if byte == 0 && self.parity_sample == 0 {
+                // Linha em 0 durante o caractere inteiro: espera voltar a 1
+                self.high_count = 0;
+                self.state = RxState::Break;
+                return;
+            }
+            self.emit(RxEvent::FramingError(byte));
+        } else if self
+            .framing
+            .parity
+            .bit(byte)
+            .is_some_and(|p| p != self.parity_sample)
+        {
+            self.emit(RxEvent::ParityError(byte));
+        } else {
+            self.emit(RxEvent::Byte(byte));
         }
+        self.state = RxState::Idle;
+        self.history.clear();
+    }
+
+    fn vote(&mut self, sample: u8) {
+        let sps = self.samples_per_symbol as f32;
+        let stop_index = self.framing.payload_bits() + 1;
+        self.bit_position += 1.;
+
+        // Cada transição é atribuída à borda de bit mais próxima. A primeira de
+        // cada borda recentra a amostragem; as seguintes são ignoradas, pois em
+        // geral são ruído logo após a transição verdadeira.
+        if sample != self.previous_sample {
+            let mut index = (self.bit_position / sps).round() as usize;
+            if sample == 1 && index == stop_index + 1 {
+                // uma subida só pode ser o início (atrasado) do bit de parada
+                index = stop_index;
+            }
+            if index > self.last_edge && index <= stop_index {
+                self.bit_position = index as f32 * sps;
+                self.last_edge = index;
+                if index == self.bit_index {
+                    // os votos até aqui eram do bit anterior
+                    self.votes_one = 0;
+                    self.votes_total = 0;
+                }
+            }
+        }
+        self.previous_sample = sample;
+
+        // Os bits de partida e de parada são decididos no meio do símbolo, para
+        // descartar logo um falso início e não invadir o próximo caractere. Se
+        // o bit de parada ainda parece 0, espera um pouco por uma subida atrasada.
+        let offset = self.bit_position - self.bit_index as f32 * sps;
+        let window_end = if self.bit_index == 0 {
+            sps / 2.
+        } else if self.bit_index == stop_index {
+            if offset < 3. * sps / 4. && 2 * self.votes_one < self.votes_total {
+                3. * sps / 4.
+            } else {
+                sps / 2.
+            }
+        } else {
+            3. * sps / 4.
+        };
+        if offset >= sps / 4. && offset < window_end {
+            self.votes_one += sample as usize;
+            self.votes_total += 1;
+        }
+        if offset < window_end {
+            return;
+        }
+
+        let bit = if 2 * self.votes_one == self.votes_total {
+            sample
+        } else {
+            (2 * self.votes_one > self.votes_total) as u8
+        };
+        self.votes_one = 0;
+        self.votes_total = 0;
+
+        let data_bits = self.framing.data_bits as usize;
+        match self.bit_index {
+            0 if bit == 1 => {
+                // falso bit de partida
+                self.state = RxState::Idle;
+                self.history.clear();
+                return;
+            }
+            0 => {}
+            i if i <= data_bits => self.current_byte |= bit << (i - 1),
+            i if i < stop_index => self.parity_sample = bit,
+            _ => {
+                self.end_of_char(bit);
+                return;
+            }
+        }
+        self.bit_index += 1;
     }
 
     pub fn put_samples(&mut self, buffer: &[u8]) {
+        let autobaud = match self.autobaud.as_mut() {
+            Some(autobaud) if self.baud_rate.is_none() => autobaud,
+            _ => {
+                self.decode(buffer);
+                return;
+            }
+        };
+
+        if let Some(baud_rate) = autobaud.put_samples(buffer) {
+            let sample_rate = autobaud.sample_rate;
+            self.baud_rate = Some(baud_rate);
+            self.set_samples_per_symbol((sample_rate as f32 / baud_rate as f32).round() as usize);
+            // O detector volta ao seu lugar depois de entregar as amostras
+            // acumuladas, para sua memória não ser liberada na callback
+            let detector = self.autobaud.take().unwrap();
+            let (older, newer) = detector.pending.as_slices();
+            self.decode(older);
+            self.decode(newer);
+            self.autobaud = Some(detector);
+        }
+    }
+
+    fn decode(&mut self, buffer: &[u8]) {
+        let data_bits = self.framing.data_bits as usize;
+        let payload_bits = self.framing.payload_bits();
+
         for &sample in buffer {
             self.history.push_back(sample);
-            if self.history.len() > 30 {
+            if self.history.len() > self.detect_window {
                 self.history.pop_front();
             }
 
             match self.state {
-                RxState::Idle => {
-                    if sample == 0 && self.history.len() == 30 {
-                        let low_count = self.history.iter().filter(|&&s| s == 0).count();
-                        if low_count >= 25 && *self.history.front().unwrap() == 0 {
-                            self.bit_index = 0;
-                            self.sample_count = 0;
-                            self.mid_bit_counter = 0;
-                            self.current_byte = 0;
-                            self.state = RxState::MidBit;
+                RxState::Idle => match self.sampling_mode {
+                    SamplingMode::MidBit => {
+                        if sample == 0 && self.history.len() == self.detect_window {
+                            let low_count = self.history.iter().filter(|&&s| s == 0).count();
+                            if low_count >= self.detect_threshold
+                                && *self.history.front().unwrap() == 0
+                            {
+                                self.start_char();
+                                self.mid_bit_counter = 0;
+                                self.state = RxState::MidBit;
+                            }
                         }
                     }
-                }
+                    SamplingMode::MajorityVote => {
+                        // Qualquer borda de descida inicia o caractere; a votação
+                        // do bit de partida descarta os falsos inícios.
+                        if sample == 0 {
+                            self.start_char();
+                            self.bit_position = 0.;
+                            self.votes_one = 0;
+                            self.votes_total = 0;
+                            self.last_edge = 0;
+                            self.previous_sample = 0;
+                            self.state = RxState::Voting;
+                        }
+                    }
+                },
 
                 RxState::MidBit => {
                     self.mid_bit_counter += 1;
-                    if self.mid_bit_counter >= 50 {
+                    if self.mid_bit_counter >= self.mid_bit_delay {
                         self.state = RxState::Receiving;
                     }
                 }
@@ -65,10 +603,14 @@ impl UartRx {
                     self.sample_count += 1;
                     if self.sample_count == (self.bit_index + 1) * self.samples_per_symbol {
                         // Pega valor no meio do símbolo
-                        self.current_byte |= sample << self.bit_index;
+                        if self.bit_index < data_bits {
+                            self.current_byte |= sample << self.bit_index;
+                        } else {
+                            self.parity_sample = sample;
+                        }
                         self.bit_index += 1;
 
-                        if self.bit_index >= 8 {
+                        if self.bit_index >= payload_bits {
                             self.state = RxState::StopBit;
                         }
                     }
@@ -76,8 +618,21 @@ impl UartRx {
 
                 RxState::StopBit => {
                     self.sample_count += 1;
-                    if self.sample_count == 9 * self.samples_per_symbol {
-                        let _ = self.to_pty.send(self.current_byte);
+                    if self.sample_count == (payload_bits + 1) * self.samples_per_symbol {
+                        self.end_of_char(sample);
+                    }
+                }
+
+                RxState::Voting => self.vote(sample),
+
+                RxState::Break => {
+                    if sample == 1 {
+                        self.high_count += 1;
+                    } else {
+                        self.high_count = 0;
+                    }
+                    if self.high_count >= self.detect_window {
+                        self.emit(RxEvent::Break);
                         self.state = RxState::Idle;
                         self.history.clear();
                     }
@@ -88,36 +643,77 @@ impl UartRx {
 }
 
 pub struct UartTx {
-    samples_per_symbol: usize,
+    /// Taxa de amostragem e taxa de sinalização; a duração de um bit é a
+    /// razão entre elas, que não precisa ser inteira
+    sample_rate: usize,
+    baud_rate: usize,
+    framing: Framing,
     samples: VecDeque<u8>,
+    /// Fração de amostra já decorrida e ainda não emitida, em unidades de
+    /// 1/(2 * baud_rate) amostra
+    phase: usize,
 }
 
 impl UartTx {
-    pub fn new(samples_per_symbol: usize) -> Self {
+    pub fn new(samples_per_symbol: usize, framing: Framing) -> Self {
+        Self::with_rates(samples_per_symbol, 1, framing)
+    }
+
+    /// Transmissor cujos bits duram em média exatamente
+    /// `sample_rate / baud_rate` amostras, alternando entre os inteiros
+    /// vizinhos quando a razão é fracionária (36,75 amostras para 1200 baud
+    /// a 44,1 kHz, por exemplo).
+    ///
+    /// A fila já nasce com um segundo de amostras, mais que o bloco de uma
+    /// callback de áudio e um caractere, para não crescer dentro dela.
+    pub fn with_rates(sample_rate: usize, baud_rate: usize, framing: Framing) -> Self {
         Self {
-            samples_per_symbol,
-            samples: VecDeque::new(),
+            sample_rate,
+            baud_rate,
+            framing,
+            samples: VecDeque::with_capacity(sample_rate),
+            phase: 0,
         }
     }
 
-    fn put_bit(&mut self, bit: u8) {
-        for _ in 0..self.samples_per_symbol {
+    /// Emite um nível pela duração dada em meios bits, levando a sobra
+    /// fracionária para o próximo.
+    fn put_bit(&mut self, bit: u8, half_bits: usize) {
+        self.phase += half_bits * self.sample_rate;
+        let num_samples = self.phase / (2 * self.baud_rate);
+        self.phase %= 2 * self.baud_rate;
+        for _ in 0..num_samples {
             self.samples.push_back(bit);
         }
     }
 
-    pub fn put_byte(&mut self, mut byte: u8) {
-        self.put_bit(0); // start bit
-        for _ in 0..8 {
-            self.put_bit(byte & 1);
-            byte >>= 1;
+    pub fn put_byte(&mut self, byte: u8) {
+        let data = byte & self.framing.data_mask();
+
+        self.put_bit(0, 2); // start bit
+        for i in 0..self.framing.data_bits {
+            self.put_bit((data >> i) & 1, 2);
+        }
+        if let Some(parity) = self.framing.parity.bit(data) {
+            self.put_bit(parity, 2);
         }
-        self.put_bit(1); // stop bit
+        // stop bit(s)
+        let stop_half_bits = match self.framing.stop_bits {
+            StopBits::One => 2,
+            StopBits::OnePointFive => 3,
+            StopBits::Two => 4,
+        };
+        self.put_bit(1, stop_half_bits);
+    }
+
+    /// Amostras dos caracteres já aceitos que ainda não foram entregues.
+    pub fn queued_samples(&self) -> usize {
+        self.samples.len()
     }
 
     pub fn get_samples(&mut self, buffer: &mut [u8]) {
-        for i in 0..buffer.len() {
-            buffer[i] = self.samples.pop_front().unwrap_or(1);
+        for sample in buffer.iter_mut() {
+            *sample = self.samples.pop_front().unwrap_or(1);
         }
     }
 }
@@ -1,17 +1,190 @@
 use std::{f32::consts::PI, ops::Rem};
-use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
 use fundsp::hacker32::U1;
 use fundsp::audionode::AudioNode;
 use fundsp::prelude::Frame;
+use crate::dtmf::Goertzel;
+
+/// Taxa de sinalização do V.21, em baud.
+pub const BAUD_RATE: usize = 300;
+
+/// Amostras por símbolo (300 baud a 48 kHz) com que as constantes do V21RX
+/// foram ajustadas.
+const REFERENCE_SAMPLES_PER_SYMBOL: usize = 160;
+
+/// Erro de fase em um símbolo, em radianos, que o V21RX tolera nos
+/// ressonadores antes de sintonizá-los de novo no desvio de frequência
+/// estimado.
+const RETUNE_PHASE: f32 = 1e-2;
+
+/// Potência de uma portadora de amplitude 1, a escala cheia.
+const FULL_SCALE_POWER: f32 = 0.5;
+
+/// Plano de frequências dos modems FSK. O V.21 usa a frequência mais baixa
+/// de cada canal para a marca (1), o Bell 103 a mais alta. O V.23 é
+/// assimétrico: quem atende transmite o canal principal de 1200 baud e quem
+/// origina responde pelo canal de retorno de 75 baud. O Bell 202 (AFSK1200
+/// do packet radio) é half-duplex e usa os mesmos tons nos dois sentidos.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Standard {
+    #[default]
+    V21,
+    Bell103,
+    V23,
+    Bell202,
+    /// DPSK de 1200 bit/s, implementado em `v22`
+    V22,
+    /// QAM de 2400 bit/s, implementado em `v22bis`
+    V22bis,
+}
+
+impl Standard {
+    /// Frequências (marca, espaço) em Hz transmitidas pelo lado que origina a
+    /// chamada ou pelo lado que atende. No V.22 e no V.22bis as duas são a
+    /// portadora.
+    pub fn frequencies(self, answer: bool) -> (f32, f32) {
+        match (self, answer) {
+            (Standard::V21, false) => (1080. - 100., 1080. + 100.),
+            (Standard::V21, true) => (1750. - 100., 1750. + 100.),
+            (Standard::Bell103, false) => (1270., 1070.),
+            (Standard::Bell103, true) => (2225., 2025.),
+            (Standard::V23, false) => (390., 450.),
+            (Standard::V23, true) => (1300., 2100.),
+            (Standard::Bell202, _) => (1200., 2200.),
+            (Standard::V22 | Standard::V22bis, answer) => {
+                let carrier = crate::v22::carrier_frequency(answer);
+                (carrier, carrier)
+            }
+        }
+    }
+
+    /// Taxa de sinalização, em baud, do canal transmitido pelo lado que
+    /// origina a chamada ou pelo lado que atende. No V.22 e no V.22bis é a
+    /// taxa de bits vista pela UART, múltipla da taxa de símbolos.
+    pub fn baud_rate(self, answer: bool) -> usize {
+        match (self, answer) {
+            (Standard::V23, false) => 75,
+            (Standard::V23, true) => 1200,
+            (Standard::Bell202, _) => 1200,
+            (Standard::V22, _) => crate::v22::BIT_RATE,
+            (Standard::V22bis, _) => crate::v22bis::BIT_RATE,
+            _ => BAUD_RATE,
+        }
+    }
+
+    /// Indica se quem atende abre a chamada com o tom de resposta do V.25.
+    /// Os modems Bell usam a própria marca do canal de resposta e o Bell 202
+    /// não estabelece chamada.
+    pub fn answer_tone(self) -> bool {
+        !matches!(self, Standard::Bell103 | Standard::Bell202)
+    }
+
+    /// Frequências angulares (omega1, omega0), na ordem em que `V21TX` e
+    /// `V21RX` as recebem.
+    pub fn omegas(self, answer: bool) -> (f32, f32) {
+        let (mark, space) = self.frequencies(answer);
+        (2. * PI * mark, 2. * PI * space)
+    }
+}
+
+impl fmt::Display for Standard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Standard::V21 => write!(f, "v21"),
+            Standard::Bell103 => write!(f, "bell103"),
+            Standard::V23 => write!(f, "v23"),
+            Standard::Bell202 => write!(f, "bell202"),
+            Standard::V22 => write!(f, "v22"),
+            Standard::V22bis => write!(f, "v22bis"),
+        }
+    }
+}
+
+impl FromStr for Standard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('.', "").as_str() {
+            "v21" => Ok(Standard::V21),
+            "bell103" => Ok(Standard::Bell103),
+            "v23" => Ok(Standard::V23),
+            "bell202" => Ok(Standard::Bell202),
+            "v22" => Ok(Standard::V22),
+            "v22bis" => Ok(Standard::V22bis),
+            _ => Err(format!(
+                "invalid standard '{}' (expected v21, bell103, v23, bell202, v22 or v22bis)",
+                s
+            )),
+        }
+    }
+}
+
+/// Parâmetros da detecção de portadora do V21RX. O AGC mede a potência de
+/// entrada, e a diferença entre as energias dos tons de marca e de espaço é
+/// comparada à que um ruído branco dessa potência deixaria num ressonador.
+/// Assim os limiares são relações sinal-ruído na banda do receptor e não
+/// dependem do nível de entrada nem da taxa de amostragem. Níveis em dB.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CarrierParams {
+    /// Limiar acima do qual a portadora passa a ser detectada
+    pub on_threshold: f32,
+    /// Limiar abaixo do qual a portadora é dada como perdida; a diferença
+    /// para `on_threshold` é a histerese
+    pub off_threshold: f32,
+    /// Menor potência de entrada, relativa à escala cheia, que o AGC
+    /// compensa; abaixo dela o ganho fica no máximo, de modo que o silêncio
+    /// e sinais muito fracos não são detectados
+    pub min_level: f32,
+    /// Constante de tempo do AGC e da média da relação medida, em símbolos
+    pub agc_symbols: f32,
+}
+
+impl Default for CarrierParams {
+    fn default() -> Self {
+        // O ruído branco só passa de 2 dB em 1 de cada 100 000 amostras; uma
+        // portadora com Eb/N0 de 10 dB fica acima de 2,4 dB em 99,9% delas
+        Self {
+            on_threshold: 3.,
+            off_threshold: 1.,
+            min_level: -70.,
+            agc_symbols: 4.,
+        }
+    }
+}
 
 pub struct V21RX {
     sampling_period: f32,
     samples_per_symbol: usize,
     omega1: f32,
     omega0: f32,
+    // Decaimento por amostra dos ressonadores, ajustado à taxa de
+    // sinalização, e limiares de detecção de portadora, como relações
+    // sinal-ruído
+    decay: f32,
+    carrier_on_threshold: f32,
+    carrier_off_threshold: f32,
+    carrier_samples: usize,
+    // AGC: potência de entrada suavizada, o piso que limita o ganho e a
+    // energia por unidade de potência que um ruído branco deixa num
+    // ressonador
+    agc_coefficient: f32,
+    min_power: f32,
+    noise_memory: f32,
+    input_power: f32,
+    // Relação sinal-ruído medida, suavizada como a potência
+    snr: f32,
+    // Coeficientes dos ressonadores dos tons de espaço e de marca, refeitos
+    // só quando o desvio de frequência estimado se afasta do usado neles
+    resonator0: Resonator,
+    resonator1: Resonator,
+    tuned_offset: f32,
 
     // Atributos auxiliares
-    sample_buffer: VecDeque<f32>,
+    // Últimas `samples_per_symbol` amostras, num anel em que a posição
+    // corrente guarda a mais antiga
+    delay_line: Vec<f32>,
+    delay_position: usize,
     v0r_buffer: f32,
     v0i_buffer: f32,
     v1r_buffer: f32,
@@ -21,6 +194,10 @@ pub struct V21RX {
     lowpass: fundsp::filter::ButterLowpass<f32, f32, U1>,
     low_difference_counter: usize,
     state: State,
+    // Desvio de frequência estimado (rad/s) somado a omega1 e omega0, para
+    // compensar portadoras que chegam deslocadas (ex.: relógios diferentes).
+    frequency_offset: f32,
+    frequency_tracked_samples: usize,
 }
 
 enum State {
@@ -28,66 +205,140 @@ enum State {
     CarrierDetected,
 }
 
+/// Coeficientes do ressonador de um tom: o decaimento de uma amostra e o de
+/// um símbolo inteiro, cada um girado pela fase que o tom avança nesse
+/// intervalo. O segundo tira da memória do ressonador a amostra que sai da
+/// janela de um símbolo.
+#[derive(Clone, Copy)]
+struct Resonator {
+    sample_re: f32,
+    sample_im: f32,
+    symbol_re: f32,
+    symbol_im: f32,
+}
+
+impl Resonator {
+    fn new(omega: f32, decay: f32, samples_per_symbol: usize, sampling_period: f32) -> Self {
+        let decay_l = decay.powi(samples_per_symbol as i32);
+        let (sample_sin, sample_cos) = (omega * sampling_period).sin_cos();
+        let (symbol_sin, symbol_cos) =
+            (omega * samples_per_symbol as f32 * sampling_period).sin_cos();
+        Self {
+            sample_re: decay * sample_cos,
+            sample_im: decay * sample_sin,
+            symbol_re: decay_l * symbol_cos,
+            symbol_im: decay_l * symbol_sin,
+        }
+    }
+
+    /// Saída do ressonador dada a amostra que entra, a que sai da janela e a
+    /// saída anterior.
+    #[inline]
+    fn step(&self, sample: f32, delayed: f32, re: f32, im: f32) -> (f32, f32) {
+        (
+            sample - self.symbol_re * delayed + self.sample_re * re - self.sample_im * im,
+            -self.symbol_im * delayed + self.sample_im * re + self.sample_re * im,
+        )
+    }
+}
+
 impl V21RX {
     pub fn new(
         sampling_period: f32,
         samples_per_symbol: usize,
         omega1: f32,
         omega0: f32,
+        params: &CarrierParams,
     ) -> Self {
-        let l = samples_per_symbol as f32;
+        // O decaimento de referência foi ajustado para 300 baud a 48 kHz.
+        // Com outras durações de símbolo, em amostras, a memória dos
+        // ressonadores acompanha essa duração.
+        let baud_rate = 1. / (samples_per_symbol as f32 * sampling_period);
+        let symbol_ratio = REFERENCE_SAMPLES_PER_SYMBOL as f32 / samples_per_symbol as f32;
+        let decay = 0.99f32.powf(symbol_ratio);
+        // Cada amostra de ruído entra no ressonador com o peso do seu
+        // decaimento, e as potências se somam ao longo da memória
+        let noise_memory =
+            (1. - decay.powi(2 * samples_per_symbol as i32)) / (1. - decay * decay);
+        let mut lowpass = fundsp::filter::ButterLowpass::new(baud_rate);
+        lowpass.set_sample_rate(1. / sampling_period as f64);
 
         Self {
             sampling_period,
             samples_per_symbol,
             omega1,
             omega0,
-            sample_buffer: VecDeque::from(vec![0.0; samples_per_symbol + 1]),
+            decay,
+            carrier_on_threshold: 10f32.powf(params.on_threshold / 10.),
+            carrier_off_threshold: 10f32.powf(params.off_threshold / 10.),
+            carrier_samples: samples_per_symbol * 5 / 16,
+            agc_coefficient: 1. - (-1. / (params.agc_symbols * samples_per_symbol as f32)).exp(),
+            min_power: FULL_SCALE_POWER * 10f32.powf(params.min_level / 10.),
+            noise_memory,
+            input_power: 0.,
+            snr: 0.,
+            resonator0: Resonator::new(omega0, decay, samples_per_symbol, sampling_period),
+            resonator1: Resonator::new(omega1, decay, samples_per_symbol, sampling_period),
+            delay_line: vec![0.0; samples_per_symbol],
+            delay_position: 0,
+            tuned_offset: 0.0,
             v0r_buffer: 0.0,
             v0i_buffer: 0.0,
             v1r_buffer: 0.0,
             v1i_buffer: 0.0,
             raw_decision_buffer: [0.0; 2],
             filtered_decision_buffer: [0.0; 2],
-            lowpass: fundsp::filter::ButterLowpass::new(300.),
+            lowpass,
             low_difference_counter: 0,
             state: State::Idle,
+            frequency_offset: 0.0,
+            frequency_tracked_samples: 0,
         }
     }
 
-    pub fn demodulate(&mut self, in_samples: &[f32], out_samples: &mut [u8]) {
-        let l = self.samples_per_symbol;
-        self.lowpass.set_sample_rate(1. / self.sampling_period as f64);
+    /// Indica se há portadora na linha (o sinal DCD).
+    pub fn carrier_detected(&self) -> bool {
+        matches!(self.state, State::CarrierDetected)
+    }
 
+    pub fn demodulate(&mut self, in_samples: &[f32], out_samples: &mut [u8]) {
         for (i, &sample) in in_samples.iter().enumerate() {
-            self.sample_buffer.push_front(sample);
-
-            let v0r = self.sample_buffer[0]
-                - 0.99f32.powi(self.samples_per_symbol as i32) * (self.omega0*self.samples_per_symbol as f32*self.sampling_period).cos() * self.sample_buffer[l]
-                + 0.99*(self.omega0*self.sampling_period).cos() * self.v0r_buffer
-                - 0.99*(self.omega0*self.sampling_period).sin() * self.v0i_buffer;
-
-            let v0i = -0.99f32.powi(self.samples_per_symbol as i32) * (self.omega0*self.samples_per_symbol as f32*self.sampling_period).sin() * self.sample_buffer[l]
-                + 0.99*(self.omega0*self.sampling_period).sin() * self.v0r_buffer
-                + 0.99*(self.omega0*self.sampling_period).cos() * self.v0i_buffer;                
-
-            let v1r = self.sample_buffer[0]
-                - 0.99f32.powi(self.samples_per_symbol as i32) * (self.omega1*self.samples_per_symbol as f32*self.sampling_period).cos() * self.sample_buffer[l]
-                + 0.99*(self.omega1*self.sampling_period).cos() * self.v1r_buffer
-                - 0.99*(self.omega1*self.sampling_period).sin() * self.v1i_buffer;
+            let delayed = self.delay_line[self.delay_position];
+            self.delay_line[self.delay_position] = sample;
+            self.delay_position += 1;
+            if self.delay_position == self.delay_line.len() {
+                self.delay_position = 0;
+            }
 
-            let v1i = -0.99f32.powi(self.samples_per_symbol as i32) * (self.omega1*self.samples_per_symbol as f32*self.sampling_period).sin() * self.sample_buffer[l]
-                + 0.99*(self.omega1*self.sampling_period).sin() * self.v1r_buffer
-                + 0.99*(self.omega1*self.sampling_period).cos() * self.v1i_buffer;
+            let (v0r, v0i) =
+                self.resonator0.step(sample, delayed, self.v0r_buffer, self.v0i_buffer);
+            let (v1r, v1i) =
+                self.resonator1.step(sample, delayed, self.v1r_buffer, self.v1i_buffer);
 
             let raw_decision = v1r * v1r + v1i * v1i - v0r * v0r - v0i * v0i;
 
             let filtered_decision = *self.lowpass.tick(&Frame::from([raw_decision])).first().unwrap();
 
+            // AGC: só a detecção de portadora depende do nível, então o
+            // ganho é aplicado à decisão, que é uma energia, e não à entrada
+            self.input_power += self.agc_coefficient * (sample * sample - self.input_power);
+            let noise_energy = self.input_power.max(self.min_power) * self.noise_memory;
+            let snr = filtered_decision.abs() / noise_energy;
+            self.snr += self.agc_coefficient * (snr - self.snr);
+
+            // O controle de frequência usa a relação instantânea, para não
+            // medir a fase nas transições entre os tons
+            if let State::CarrierDetected = self.state {
+                if snr > self.carrier_on_threshold {
+                    self.track_frequency(filtered_decision > 0.0, [v1r, v1i, v0r, v0i]);
+                }
+            }
+
             out_samples[i] = match self.state {
                 State::Idle => {
-                    if filtered_decision.abs() > 120.0 {
+                    if self.snr > self.carrier_on_threshold {
                         self.low_difference_counter = 0;
+                        self.frequency_tracked_samples = 0;
                         self.state = State::CarrierDetected;
                         if filtered_decision > 0.0 { 1 } else { 0 }
                     } else {
@@ -95,13 +346,13 @@ impl V21RX {
                     }
                 }
                 State::CarrierDetected => {
-                    if filtered_decision.abs() < 60.0 {
+                    if self.snr < self.carrier_off_threshold {
                         self.low_difference_counter += 1;
                     } else {
                         self.low_difference_counter = 0;
                     }
 
-                    if self.low_difference_counter >= 50 {
+                    if self.low_difference_counter >= self.carrier_samples {
                         self.state = State::Idle;
                         1
                     } else {
@@ -110,7 +361,6 @@ impl V21RX {
                 }
             };
 
-            self.sample_buffer.pop_back();
             self.v0r_buffer = v0r;
             self.v0i_buffer = v0i;
             self.v1r_buffer = v1r;
@@ -121,6 +371,61 @@ impl V21RX {
             self.filtered_decision_buffer[0] = filtered_decision;
         }
     }
+
+    /// Controle automático de frequência: enquanto um dos tons domina, mede o
+    /// avanço de fase da saída do ressonador correspondente (que gira na
+    /// frequência recebida) e aproxima lentamente o desvio estimado do medido.
+    ///
+    /// Relógios de amostragem 2% diferentes deslocam os tons de resposta do
+    /// Bell 103 em até 45 Hz, quase um quarto da distância entre a marca e o
+    /// espaço; sem a correção a decisão entre os ressonadores perde margem e
+    /// a taxa de erros fica perto de 1e-3 mesmo com Eb/N0 de 19 dB.
+    fn track_frequency(&mut self, mark: bool, [v1r, v1i, v0r, v0i]: [f32; 4]) {
+        // Durante o primeiro símbolo após a detecção da portadora os
+        // ressonadores ainda estão no transitório. Depois disso o ganho decai
+        // como uma média acumulada, para convergir ainda no preâmbulo de
+        // marca, e por fim fica fixo e lento, para não reagir ao ruído.
+        let l = self.samples_per_symbol;
+        self.frequency_tracked_samples += 1;
+        if self.frequency_tracked_samples <= l {
+            return;
+        }
+        let step = 1. / self.frequency_tracked_samples.min(16 * l) as f32;
+
+        let (re, im, prev_re, prev_im, omega) = if mark {
+            (v1r, v1i, self.v1r_buffer, self.v1i_buffer, self.omega1)
+        } else {
+            (v0r, v0i, self.v0r_buffer, self.v0i_buffer, self.omega0)
+        };
+
+        let phase_step = (im * prev_re - re * prev_im).atan2(re * prev_re + im * prev_im);
+        let error = phase_step / self.sampling_period - omega - self.frequency_offset;
+
+        let max_offset = 0.05 * self.omega0.min(self.omega1);
+        self.frequency_offset =
+            (self.frequency_offset + step * error).clamp(-max_offset, max_offset);
+
+        // O desvio muda um pouco a cada amostra; refazer os coeficientes,
+        // com seus senos e cossenos, só compensa quando a diferença para o
+        // desvio usado neles já gira a fase em um símbolo de modo apreciável
+        let drift = (self.frequency_offset - self.tuned_offset).abs();
+        if drift * l as f32 * self.sampling_period > RETUNE_PHASE {
+            self.tuned_offset = self.frequency_offset;
+            self.resonator0 = self.resonator(self.omega0);
+            self.resonator1 = self.resonator(self.omega1);
+        }
+    }
+
+    /// Ressonador do tom de frequência nominal dada, corrigida pelo desvio
+    /// estimado.
+    fn resonator(&self, omega: f32) -> Resonator {
+        Resonator::new(
+            omega + self.tuned_offset,
+            self.decay,
+            self.samples_per_symbol,
+            self.sampling_period,
+        )
+    }
 }
 
 pub struct V21TX {
@@ -155,3 +460,161 @@ impl V21TX {
         }
     }
 }
+
+/// Frequência do tom de resposta do V.25, em Hz.
+pub const ANSWER_TONE_FREQUENCY: f32 = 2100.;
+
+/// Durações, em segundos, da sequência de quem atende no V.25: silêncio de
+/// tarifação (1,8 a 2,5 s), tom de resposta (2,6 a 4 s) e pausa antes dos
+/// dados.
+const BILLING_DELAY: f32 = 2.;
+const ANSWER_TONE_DURATION: f32 = 3.3;
+const ANSWER_TONE_GAP: f32 = 0.075;
+
+/// Intervalo, em segundos, entre as inversões de fase opcionais do tom de
+/// resposta, que desabilitam os canceladores de eco da rede.
+const PHASE_REVERSAL_INTERVAL: f32 = 0.45;
+
+/// Gera a sequência do V.25 que quem atende transmite antes dos dados.
+pub struct AnswerTone {
+    sampling_period: f32,
+    phase_reversals: bool,
+
+    // Atributos auxiliares
+    elapsed_samples: usize,
+    phase: f32,
+}
+
+impl AnswerTone {
+    pub fn new(sampling_period: f32, phase_reversals: bool) -> Self {
+        Self {
+            sampling_period,
+            phase_reversals,
+            elapsed_samples: 0,
+            phase: 0.,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.elapsed_samples as f32 * self.sampling_period
+            >= BILLING_DELAY + ANSWER_TONE_DURATION + ANSWER_TONE_GAP
+    }
+
+    /// Preenche o início de `out_samples` com o que resta da sequência e
+    /// devolve quantas amostras ela ocupou; depois do fim, devolve 0.
+    pub fn generate(&mut self, out_samples: &mut [f32]) -> usize {
+        let omega = 2. * PI * ANSWER_TONE_FREQUENCY;
+        let reversal_samples = (PHASE_REVERSAL_INTERVAL / self.sampling_period).round() as usize;
+        let tone_start = (BILLING_DELAY / self.sampling_period).round() as usize;
+        let tone_end = tone_start + (ANSWER_TONE_DURATION / self.sampling_period).round() as usize;
+
+        let mut written = 0;
+        for out in out_samples.iter_mut() {
+            if self.is_done() {
+                break;
+            }
+            let n = self.elapsed_samples;
+            *out = if (tone_start..tone_end).contains(&n) {
+                let reversal = n > tone_start && (n - tone_start) % reversal_samples == 0;
+                if self.phase_reversals && reversal {
+                    self.phase = (self.phase + PI).rem(2. * PI);
+                }
+                let sample = self.phase.sin();
+                self.phase = (self.phase + self.sampling_period * omega).rem(2. * PI);
+                sample
+            } else {
+                0.
+            };
+            self.elapsed_samples += 1;
+            written += 1;
+        }
+        written
+    }
+}
+
+/// Duração, em segundos, dos blocos analisados pelo detector de tom de
+/// resposta; a resolução em frequência é o inverso disso.
+const DETECTOR_BLOCK: f32 = 0.01;
+
+/// Blocos seguidos com e sem o tom para o detector mudar de estado.
+const DETECTOR_ON_BLOCKS: usize = 20;
+const DETECTOR_OFF_BLOCKS: usize = 5;
+
+/// Fração mínima da energia do bloco que precisa estar no tom.
+const DETECTOR_RATIO: f32 = 0.6;
+
+/// Potência média mínima do bloco, abaixo da qual ele é tratado como
+/// silêncio.
+const DETECTOR_MIN_POWER: f32 = 1e-4;
+
+/// Detecta o tom de resposta de 2100 Hz do V.25. Um filtro de Goertzel
+/// mede, em blocos de 10 ms, a energia em 2100 Hz em relação à energia total
+/// do bloco, de modo que as portadoras de dados e os tons de guarda, cuja
+/// energia se espalha ou cai fora da raia, não o disparam.
+pub struct AnswerToneDetector {
+    block_samples: usize,
+    filter: Goertzel,
+
+    // Atributos auxiliares
+    energy: f32,
+    count: usize,
+    on_blocks: usize,
+    off_blocks: usize,
+    detected: bool,
+}
+
+impl AnswerToneDetector {
+    pub fn new(sampling_period: f32) -> Self {
+        let block_samples = (DETECTOR_BLOCK / sampling_period).round() as usize;
+        Self {
+            block_samples,
+            filter: Goertzel::new(ANSWER_TONE_FREQUENCY, sampling_period),
+            energy: 0.,
+            count: 0,
+            on_blocks: 0,
+            off_blocks: 0,
+            detected: false,
+        }
+    }
+
+    /// Indica se o tom está presente.
+    pub fn detected(&self) -> bool {
+        self.detected
+    }
+
+    pub fn put_samples(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            self.filter.put_sample(sample);
+            self.energy += sample * sample;
+            self.count += 1;
+            if self.count == self.block_samples {
+                self.end_block();
+            }
+        }
+    }
+
+    fn end_block(&mut self) {
+        // A energia de um tom puro de amplitude A num bloco de N amostras é
+        // A² N / 2
+        let n = self.block_samples as f32;
+        let amplitude = self.filter.take_amplitude(self.block_samples);
+        let present = self.energy > DETECTOR_MIN_POWER * n
+            && amplitude * amplitude * n / 2. > DETECTOR_RATIO * self.energy;
+
+        if present {
+            self.on_blocks += 1;
+            self.off_blocks = 0;
+        } else {
+            self.off_blocks += 1;
+            self.on_blocks = 0;
+        }
+        if self.on_blocks >= DETECTOR_ON_BLOCKS {
+            self.detected = true;
+        } else if self.off_blocks >= DETECTOR_OFF_BLOCKS {
+            self.detected = false;
+        }
+
+        self.energy = 0.;
+        self.count = 0;
+    }
+}
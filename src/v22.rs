@@ -0,0 +1,464 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+/// Taxa de modulação do V.22, em baud.
+pub const BAUD_RATE: usize = 600;
+
+/// Taxa de dados do V.22 (2 bits por símbolo), em bit/s.
+pub const BIT_RATE: usize = 2 * BAUD_RATE;
+
+/// Fator de roll-off do cosseno levantado, dividido entre TX e RX.
+const ROLLOFF: f32 = 0.75;
+
+/// Duração do pulso de raiz de cosseno levantado, em símbolos.
+pub(crate) const SPAN: usize = 8;
+
+/// Pontos da tabela do pulso por símbolo.
+const TABLE_RESOLUTION: usize = 64;
+
+/// Nível do sinal de dados na linha.
+pub(crate) const DATA_LEVEL: f32 = 0.5;
+
+/// Símbolos após a detecção de portadora durante os quais a saída fica presa
+/// em 1, enquanto os laços de relógio e portadora convergem.
+const TRAINING_SYMBOLS: usize = 64;
+
+/// Portadora transmitida pelo lado que origina a chamada ou pelo que atende.
+pub fn carrier_frequency(answer: bool) -> f32 {
+    if answer {
+        2400.
+    } else {
+        1200.
+    }
+}
+
+/// Tom de guarda opcional transmitido por quem atende, junto da portadora de
+/// 2400 Hz.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GuardTone {
+    #[default]
+    None,
+    /// 550 Hz, 3 dB abaixo do sinal de dados
+    Hz550,
+    /// 1800 Hz, 6 dB abaixo do sinal de dados
+    Hz1800,
+}
+
+impl GuardTone {
+    /// (frequência em Hz, amplitude) do tom, se houver.
+    pub(crate) fn tone(self) -> Option<(f32, f32)> {
+        match self {
+            GuardTone::None => None,
+            GuardTone::Hz550 => Some((550., DATA_LEVEL * 10f32.powf(-3. / 20.))),
+            GuardTone::Hz1800 => Some((1800., DATA_LEVEL * 10f32.powf(-6. / 20.))),
+        }
+    }
+}
+
+impl fmt::Display for GuardTone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuardTone::None => write!(f, "none"),
+            GuardTone::Hz550 => write!(f, "550"),
+            GuardTone::Hz1800 => write!(f, "1800"),
+        }
+    }
+}
+
+impl FromStr for GuardTone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().trim_end_matches("hz") {
+            "none" => Ok(GuardTone::None),
+            "550" => Ok(GuardTone::Hz550),
+            "1800" => Ok(GuardTone::Hz1800),
+            _ => Err(format!(
+                "invalid guard tone '{}' (expected none, 550 or 1800)",
+                s
+            )),
+        }
+    }
+}
+
+/// Pulso de raiz de cosseno levantado com energia unitária, tabelado em
+/// função do tempo em símbolos.
+pub(crate) struct Pulse {
+    table: Vec<f32>,
+}
+
+impl Pulse {
+    pub(crate) fn new() -> Self {
+        let half = SPAN as f32 / 2.;
+        let table = (0..=SPAN * TABLE_RESOLUTION)
+            .map(|i| Self::rrc(i as f32 / TABLE_RESOLUTION as f32 - half))
+            .collect();
+        Self { table }
+    }
+
+    fn rrc(t: f32) -> f32 {
+        let b = ROLLOFF;
+        if t.abs() < 1e-6 {
+            return 1. - b + 4. * b / PI;
+        }
+        if (t.abs() - 1. / (4. * b)).abs() < 1e-6 {
+            let a = PI / (4. * b);
+            return b / 2f32.sqrt() * ((1. + 2. / PI) * a.sin() + (1. - 2. / PI) * a.cos());
+        }
+        ((PI * t * (1. - b)).sin() + 4. * b * t * (PI * t * (1. + b)).cos())
+            / (PI * t * (1. - (4. * b * t).powi(2)))
+    }
+
+    /// Valor do pulso centrado em `SPAN / 2` símbolos.
+    pub(crate) fn at(&self, t: f32) -> f32 {
+        let x = t * TABLE_RESOLUTION as f32;
+        if x <= 0. || x >= (self.table.len() - 1) as f32 {
+            return 0.;
+        }
+        let i = x as usize;
+        let frac = x - i as f32;
+        self.table[i] + frac * (self.table[i + 1] - self.table[i])
+    }
+}
+
+/// Embaralhador autossincronizante 1 + x^-14 + x^-17 do V.22, com a
+/// inversão forçada após 64 uns consecutivos na saída.
+#[derive(Default)]
+pub(crate) struct Scrambler {
+    history: u32,
+    ones: usize,
+}
+
+impl Scrambler {
+    pub(crate) fn scramble(&mut self, bit: u8) -> u8 {
+        let mut out = bit ^ ((self.history >> 13) as u8 & 1) ^ ((self.history >> 16) as u8 & 1);
+        if self.ones == 64 {
+            out ^= 1;
+        }
+        self.ones = if out == 1 { (self.ones % 64) + 1 } else { 0 };
+        self.history = (self.history << 1) | out as u32;
+        out
+    }
+
+    pub(crate) fn descramble(&mut self, bit: u8) -> u8 {
+        let mut out = bit ^ ((self.history >> 13) as u8 & 1) ^ ((self.history >> 16) as u8 & 1);
+        if self.ones == 64 {
+            out ^= 1;
+        }
+        self.ones = if bit == 1 { (self.ones % 64) + 1 } else { 0 };
+        self.history = (self.history << 1) | bit as u32;
+        out
+    }
+}
+
+/// Salto de fase, em quartos de volta, de cada dibit (primeiro bit, segundo
+/// bit) segundo a tabela 1 do V.22.
+pub(crate) fn dibit_to_quadrant(b0: u8, b1: u8) -> usize {
+    match (b0, b1) {
+        (0, 0) => 1,
+        (0, 1) => 0,
+        (1, 1) => 3,
+        _ => 2,
+    }
+}
+
+pub(crate) fn quadrant_to_dibit(quadrant: usize) -> [u8; 2] {
+    match quadrant & 3 {
+        0 => [0, 1],
+        1 => [0, 0],
+        2 => [1, 0],
+        _ => [1, 1],
+    }
+}
+
+pub struct V22TX {
+    sampling_period: f32,
+    omega: f32,
+    guard_tone: Option<(f32, f32)>,
+    pulse: Pulse,
+    scrambler: Scrambler,
+
+    // Atributos auxiliares
+    bit_phase: f32,
+    symbol_phase: f32,
+    bits: [u8; 2],
+    bit_count: usize,
+    quadrant: usize,
+    next_symbol: (f32, f32),
+    symbols: VecDeque<(f32, f32)>,
+    phase: f32,
+    guard_phase: f32,
+}
+
+impl V22TX {
+    pub fn new(sampling_period: f32, omega: f32, guard_tone: GuardTone) -> Self {
+        let start = (0.5f32.sqrt(), 0.5f32.sqrt());
+        Self {
+            sampling_period,
+            omega,
+            guard_tone: guard_tone.tone(),
+            pulse: Pulse::new(),
+            scrambler: Scrambler::default(),
+            bit_phase: 0.,
+            symbol_phase: 0.,
+            bits: [1; 2],
+            bit_count: 0,
+            quadrant: 0,
+            next_symbol: start,
+            symbols: VecDeque::from(vec![(0., 0.); SPAN + 1]),
+            phase: 0.,
+            guard_phase: 0.,
+        }
+    }
+
+    pub fn modulate(&mut self, in_samples: &[u8], out_samples: &mut [f32]) {
+        debug_assert!(in_samples.len() == out_samples.len());
+
+        let bit_step = BIT_RATE as f32 * self.sampling_period;
+        let symbol_step = BAUD_RATE as f32 * self.sampling_period;
+
+        for (&level, out) in in_samples.iter().zip(out_samples.iter_mut()) {
+            // Cada bit é lido no meio do seu intervalo; o símbolo formado por
+            // um dibit entra no filtro no início do período seguinte.
+            let previous = self.bit_phase;
+            self.bit_phase += bit_step;
+            if previous < 0.5 && self.bit_phase >= 0.5 {
+                self.bits[self.bit_count] = self.scrambler.scramble(level);
+                self.bit_count += 1;
+                if self.bit_count == 2 {
+                    self.bit_count = 0;
+                    self.quadrant =
+                        (self.quadrant + dibit_to_quadrant(self.bits[0], self.bits[1])) & 3;
+                    let angle = PI / 4. + self.quadrant as f32 * PI / 2.;
+                    self.next_symbol = (angle.cos(), angle.sin());
+                }
+            }
+            if self.bit_phase >= 1. {
+                self.bit_phase -= 1.;
+            }
+
+            self.symbol_phase += symbol_step;
+            if self.symbol_phase >= 1. {
+                self.symbol_phase -= 1.;
+                self.symbols.pop_front();
+                self.symbols.push_back(self.next_symbol);
+            }
+
+            let (mut re, mut im) = (0., 0.);
+            for (m, &(sr, si)) in self.symbols.iter().rev().enumerate() {
+                let g = self.pulse.at(self.symbol_phase + m as f32);
+                re += sr * g;
+                im += si * g;
+            }
+
+            *out = DATA_LEVEL * (re * self.phase.cos() - im * self.phase.sin());
+            self.phase = (self.phase + self.omega * self.sampling_period) % (2. * PI);
+
+            if let Some((frequency, amplitude)) = self.guard_tone {
+                *out += amplitude * self.guard_phase.sin();
+                self.guard_phase =
+                    (self.guard_phase + 2. * PI * frequency * self.sampling_period) % (2. * PI);
+            }
+        }
+    }
+}
+
+enum State {
+    Idle,
+    CarrierDetected,
+}
+
+pub struct V22RX {
+    sampling_period: f32,
+    omega: f32,
+    samples_per_symbol: f32,
+    pulse: Pulse,
+    descrambler: Scrambler,
+
+    // Atributos auxiliares
+    phase: f32,
+    baseband: VecDeque<(f32, f32)>,
+    power: f32,
+    state: State,
+    // Recuperação de relógio de símbolo (Gardner)
+    countdown: f32,
+    period: f32,
+    at_midpoint: bool,
+    midpoint: (f32, f32),
+    last_symbol: (f32, f32),
+    // Recuperação de portadora
+    carrier_phase: f32,
+    carrier_frequency: f32,
+    last_point: Option<(f32, f32)>,
+    // Saída
+    training: usize,
+    out_bits: [u8; 2],
+    out_counter: f32,
+}
+
+impl V22RX {
+    pub fn new(sampling_period: f32, omega: f32) -> Self {
+        let samples_per_symbol = 1. / (BAUD_RATE as f32 * sampling_period);
+        let taps = (SPAN as f32 * samples_per_symbol).ceil() as usize + 1;
+        Self {
+            sampling_period,
+            omega,
+            samples_per_symbol,
+            pulse: Pulse::new(),
+            descrambler: Scrambler::default(),
+            phase: 0.,
+            baseband: VecDeque::from(vec![(0., 0.); taps]),
+            power: 0.,
+            state: State::Idle,
+            countdown: samples_per_symbol / 2.,
+            period: samples_per_symbol,
+            at_midpoint: false,
+            midpoint: (0., 0.),
+            last_symbol: (0., 0.),
+            carrier_phase: 0.,
+            carrier_frequency: 0.,
+            last_point: None,
+            training: TRAINING_SYMBOLS,
+            out_bits: [1; 2],
+            out_counter: 0.,
+        }
+    }
+
+    /// Saída do filtro casado `frac` amostras antes da amostra mais recente,
+    /// atrasada de meio pulso.
+    fn matched_filter(&self, frac: f32) -> (f32, f32) {
+        let (mut re, mut im) = (0., 0.);
+        for (i, &(zr, zi)) in self.baseband.iter().rev().enumerate() {
+            let g = self.pulse.at((i as f32 - frac) / self.samples_per_symbol);
+            re += zr * g;
+            im += zi * g;
+        }
+        (re / self.samples_per_symbol, im / self.samples_per_symbol)
+    }
+
+    fn reset(&mut self) {
+        self.period = self.samples_per_symbol;
+        self.carrier_phase = 0.;
+        self.carrier_frequency = 0.;
+        self.last_point = None;
+        self.training = TRAINING_SYMBOLS;
+        self.out_bits = [1; 2];
+    }
+
+    /// Indica se há portadora na linha (o sinal DCD).
+    pub fn carrier_detected(&self) -> bool {
+        matches!(self.state, State::CarrierDetected)
+    }
+
+    pub fn demodulate(&mut self, in_samples: &[f32], out_samples: &mut [u8]) {
+        debug_assert!(in_samples.len() == out_samples.len());
+
+        for (&sample, out) in in_samples.iter().zip(out_samples.iter_mut()) {
+            self.baseband.pop_front();
+            self.baseband
+                .push_back((sample * self.phase.cos(), -sample * self.phase.sin()));
+            self.phase = (self.phase + self.omega * self.sampling_period) % (2. * PI);
+
+            self.countdown -= 1.;
+            if self.countdown <= 0. {
+                let y = self.matched_filter(-self.countdown);
+                self.countdown += self.period / 2.;
+                if self.at_midpoint {
+                    self.midpoint = y;
+                } else {
+                    self.symbol(y);
+                }
+                self.at_midpoint = !self.at_midpoint;
+            }
+
+            *out = if self.out_counter < self.period / 2. {
+                self.out_bits[0]
+            } else {
+                self.out_bits[1]
+            };
+            self.out_counter += 1.;
+        }
+    }
+
+    fn symbol(&mut self, y: (f32, f32)) {
+        let energy = y.0 * y.0 + y.1 * y.1;
+        self.power += 0.05 * (energy - self.power);
+
+        // Limiares de portadora em relação ao nível nominal do sinal no
+        // filtro casado, (DATA_LEVEL / 2)²
+        let nominal = (DATA_LEVEL / 2.).powi(2);
+        match self.state {
+            State::Idle => {
+                if self.power > 0.1 * nominal {
+                    self.state = State::CarrierDetected;
+                    self.reset();
+                }
+            }
+            State::CarrierDetected => {
+                if self.power < 0.05 * nominal {
+                    self.state = State::Idle;
+                    self.reset();
+                }
+            }
+        }
+
+        // Gardner: o erro é nulo quando o ponto médio cai na transição
+        let (mr, mi) = self.midpoint;
+        let (pr, pi) = self.last_symbol;
+        let timing_error = ((pr - y.0) * mr + (pi - y.1) * mi) / self.power.max(1e-12);
+        self.last_symbol = y;
+
+        if let State::Idle = self.state {
+            self.out_bits = [1; 2];
+            self.out_counter = 0.;
+            return;
+        }
+
+        // Durante o treinamento os laços são mais largos, para adquirir
+        // rapidamente desvios de relógio e de frequência
+        let (timing_gear, carrier_gear): (f32, f32) = if self.training > 0 {
+            (3., 2.)
+        } else {
+            (1., 1.)
+        };
+
+        let timing_error = timing_error.clamp(-1., 1.);
+        self.countdown += 0.05 * timing_gear * timing_error * self.samples_per_symbol;
+        self.period += 0.002 * timing_gear.powi(2) * timing_error * self.samples_per_symbol;
+        self.period = self.period.clamp(
+            0.95 * self.samples_per_symbol,
+            1.05 * self.samples_per_symbol,
+        );
+
+        // Portadora: laço de segunda ordem sobre a quarta potência, que é
+        // indiferente ao símbolo transmitido
+        let (c, s) = (self.carrier_phase.cos(), self.carrier_phase.sin());
+        let z = (y.0 * c + y.1 * s, y.1 * c - y.0 * s);
+        let z2 = (z.0 * z.0 - z.1 * z.1, 2. * z.0 * z.1);
+        let z4 = (z2.0 * z2.0 - z2.1 * z2.1, 2. * z2.0 * z2.1);
+        let phase_error = (-z4.1).atan2(-z4.0) / 4.;
+        self.carrier_frequency += 0.01 * carrier_gear.powi(2) * phase_error;
+        self.carrier_phase =
+            (self.carrier_phase + self.carrier_frequency + 0.2 * carrier_gear * phase_error)
+                % (2. * PI);
+
+        if let Some(last) = self.last_point {
+            let d = (z.0 * last.0 + z.1 * last.1, z.1 * last.0 - z.0 * last.1);
+            let quadrant = (d.1.atan2(d.0) / (PI / 2.)).round() as i32;
+            let [b0, b1] = quadrant_to_dibit(quadrant.rem_euclid(4) as usize);
+            let bits = [
+                self.descrambler.descramble(b0),
+                self.descrambler.descramble(b1),
+            ];
+            if self.training > 0 {
+                self.training -= 1;
+            } else {
+                self.out_bits = bits;
+            }
+            self.out_counter = 0.;
+        }
+        self.last_point = Some(z);
+    }
+}
@@ -0,0 +1,487 @@
+use crate::v22::{
+    dibit_to_quadrant, quadrant_to_dibit, GuardTone, Pulse, Scrambler, DATA_LEVEL, SPAN,
+};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+/// Taxa de modulação do V.22bis, a mesma do V.22, em baud.
+pub const BAUD_RATE: usize = crate::v22::BAUD_RATE;
+
+/// Taxa de dados do V.22bis (4 bits por símbolo), em bit/s.
+pub const BIT_RATE: usize = 4 * BAUD_RATE;
+
+/// Duração, em símbolos, das fases da sequência de treinamento: S1 (dibits
+/// 00 e 11 alternados, sem embaralhar, por 100 ms), uns embaralhados a
+/// 1200 bit/s por 450 ms e uns embaralhados a 2400 bit/s por 200 ms.
+const S1_SYMBOLS: usize = 60;
+const SCRAMBLED_1200_SYMBOLS: usize = 270;
+const SCRAMBLED_2400_SYMBOLS: usize = 120;
+
+/// Duração total do treinamento, em bits a 2400 bit/s. Dados entregues ao
+/// transmissor antes disso são descartados.
+pub const TRAINING_BITS: usize = 4 * (S1_SYMBOLS + SCRAMBLED_1200_SYMBOLS + SCRAMBLED_2400_SYMBOLS);
+
+/// Alternâncias seguidas que o receptor precisa ver para reconhecer o S1.
+const S1_DETECT_SYMBOLS: usize = 16;
+
+/// Símbolos antes do fim da fase de 1200 bit/s em que o receptor já passa a
+/// decidir com 16 pontos, cobrindo o atraso na detecção do fim do S1. Os
+/// quatro pontos usados a 1200 bit/s fazem parte da constelação completa.
+const SWITCH_MARGIN_SYMBOLS: usize = 8;
+
+/// Coeficientes do equalizador, espaçados de um símbolo.
+const EQUALIZER_TAPS: usize = 7;
+
+/// Energia média da constelação de 16 pontos, em unidades da grade ±1, ±3.
+const CONSTELLATION_ENERGY: f32 = 10.;
+
+/// Ponto do primeiro quadrante, em unidades da grade, selecionado por Q3Q4;
+/// os demais quadrantes são rotações dele. A 1200 bit/s Q3Q4 é sempre 01.
+fn first_quadrant_point(q3: u8, q4: u8) -> (f32, f32) {
+    (if q4 == 1 { 3. } else { 1. }, if q3 == 1 { 3. } else { 1. })
+}
+
+/// Gira `point` de `quadrant` quartos de volta no sentido anti-horário.
+fn rotate((x, y): (f32, f32), quadrant: usize) -> (f32, f32) {
+    match quadrant & 3 {
+        0 => (x, y),
+        1 => (-y, x),
+        2 => (-x, -y),
+        _ => (y, -x),
+    }
+}
+
+fn quadrant((x, y): (f32, f32)) -> usize {
+    match (x >= 0., y >= 0.) {
+        (true, true) => 0,
+        (false, true) => 1,
+        (false, false) => 2,
+        (true, false) => 3,
+    }
+}
+
+/// Ponto da constelação de 16 pontos mais próximo de `z`.
+fn decide_16((x, y): (f32, f32)) -> (f32, f32) {
+    let slice = |v: f32| (2. * (v / 2.).floor() + 1.).clamp(-3., 3.);
+    (slice(x), slice(y))
+}
+
+/// Ponto da constelação de 1200 bit/s (Q3Q4 = 01) mais próximo de `z`.
+fn decide_4(z: (f32, f32)) -> (f32, f32) {
+    (0..4)
+        .map(|q| rotate(first_quadrant_point(0, 1), q))
+        .max_by(|a, b| {
+            let pa = z.0 * a.0 + z.1 * a.1;
+            let pb = z.0 * b.0 + z.1 * b.1;
+            pa.total_cmp(&pb)
+        })
+        .unwrap()
+}
+
+fn mul(a: (f32, f32), b: (f32, f32)) -> (f32, f32) {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+fn conj(a: (f32, f32)) -> (f32, f32) {
+    (a.0, -a.1)
+}
+
+pub struct V22bisTX {
+    sampling_period: f32,
+    omega: f32,
+    guard_tone: Option<(f32, f32)>,
+    pulse: Pulse,
+    scrambler: Scrambler,
+
+    // Atributos auxiliares
+    symbol_phase: f32,
+    bits: [u8; 4],
+    symbol_count: usize,
+    quadrant: usize,
+    symbols: VecDeque<(f32, f32)>,
+    phase: f32,
+    guard_phase: f32,
+}
+
+impl V22bisTX {
+    pub fn new(sampling_period: f32, omega: f32, guard_tone: GuardTone) -> Self {
+        Self {
+            sampling_period,
+            omega,
+            guard_tone: guard_tone.tone(),
+            pulse: Pulse::new(),
+            scrambler: Scrambler::default(),
+            symbol_phase: 0.,
+            bits: [1; 4],
+            symbol_count: 0,
+            quadrant: 0,
+            symbols: VecDeque::from(vec![(0., 0.); SPAN + 1]),
+            phase: 0.,
+            guard_phase: 0.,
+        }
+    }
+
+    /// Próximo símbolo, da sequência de treinamento ou dos bits lidos
+    /// durante o período anterior.
+    fn next_symbol(&mut self) -> (f32, f32) {
+        let count = self.symbol_count;
+        self.symbol_count = self.symbol_count.saturating_add(1);
+
+        let [q1, q2, q3, q4] = if count < S1_SYMBOLS {
+            let bit = (count % 2) as u8;
+            [bit, bit, 0, 1]
+        } else if count < S1_SYMBOLS + SCRAMBLED_1200_SYMBOLS {
+            [self.scrambler.scramble(1), self.scrambler.scramble(1), 0, 1]
+        } else if count < S1_SYMBOLS + SCRAMBLED_1200_SYMBOLS + SCRAMBLED_2400_SYMBOLS {
+            [1; 4].map(|bit| self.scrambler.scramble(bit))
+        } else {
+            self.bits.map(|bit| self.scrambler.scramble(bit))
+        };
+
+        self.quadrant = (self.quadrant + dibit_to_quadrant(q1, q2)) & 3;
+        let (x, y) = rotate(first_quadrant_point(q3, q4), self.quadrant);
+        let scale = CONSTELLATION_ENERGY.sqrt().recip();
+        (x * scale, y * scale)
+    }
+
+    pub fn modulate(&mut self, in_samples: &[u8], out_samples: &mut [f32]) {
+        debug_assert!(in_samples.len() == out_samples.len());
+
+        let symbol_step = BAUD_RATE as f32 * self.sampling_period;
+
+        for (&level, out) in in_samples.iter().zip(out_samples.iter_mut()) {
+            // Os quatro bits de cada símbolo são lidos no meio de seus
+            // intervalos; o símbolo entra no filtro no início do período
+            // seguinte.
+            let previous = self.symbol_phase;
+            self.symbol_phase += symbol_step;
+            for (k, bit) in self.bits.iter_mut().enumerate() {
+                let t = (k as f32 + 0.5) / 4.;
+                if previous < t && self.symbol_phase >= t {
+                    *bit = level;
+                }
+            }
+            if self.symbol_phase >= 1. {
+                self.symbol_phase -= 1.;
+                let symbol = self.next_symbol();
+                self.symbols.pop_front();
+                self.symbols.push_back(symbol);
+            }
+
+            let (mut re, mut im) = (0., 0.);
+            for (m, &(sr, si)) in self.symbols.iter().rev().enumerate() {
+                let g = self.pulse.at(self.symbol_phase + m as f32);
+                re += sr * g;
+                im += si * g;
+            }
+
+            *out = DATA_LEVEL * (re * self.phase.cos() - im * self.phase.sin());
+            self.phase = (self.phase + self.omega * self.sampling_period) % (2. * PI);
+
+            if let Some((frequency, amplitude)) = self.guard_tone {
+                *out += amplitude * self.guard_phase.sin();
+                self.guard_phase =
+                    (self.guard_phase + 2. * PI * frequency * self.sampling_period) % (2. * PI);
+            }
+        }
+    }
+}
+
+enum State {
+    Idle,
+    CarrierDetected,
+}
+
+/// Fase da sequência de treinamento em que o receptor se encontra.
+#[derive(Clone, Copy)]
+enum Stage {
+    /// Aguardando o S1; conta as alternâncias de fase já vistas
+    S1(usize),
+    /// Uns embaralhados; conta os símbolos desde o fim do S1
+    Training(usize),
+    Data,
+}
+
+pub struct V22bisRX {
+    sampling_period: f32,
+    omega: f32,
+    samples_per_symbol: f32,
+    pulse: Pulse,
+    descrambler: Scrambler,
+
+    // Atributos auxiliares
+    phase: f32,
+    baseband: VecDeque<(f32, f32)>,
+    power: f32,
+    state: State,
+    stage: Stage,
+    // Recuperação de relógio de símbolo (Gardner)
+    countdown: f32,
+    period: f32,
+    at_midpoint: bool,
+    midpoint: (f32, f32),
+    last_symbol: (f32, f32),
+    // Equalizador
+    equalizer_line: VecDeque<(f32, f32)>,
+    taps: [(f32, f32); EQUALIZER_TAPS],
+    // Recuperação de portadora
+    carrier_phase: f32,
+    carrier_frequency: f32,
+    last_quadrant: usize,
+    last_step: usize,
+    last_z: (f32, f32),
+    last_jump: (f32, f32),
+    // Saída
+    out_bits: [u8; 4],
+    out_counter: f32,
+}
+
+impl V22bisRX {
+    pub fn new(sampling_period: f32, omega: f32) -> Self {
+        let samples_per_symbol = 1. / (BAUD_RATE as f32 * sampling_period);
+        let taps = (SPAN as f32 * samples_per_symbol).ceil() as usize + 1;
+        Self {
+            sampling_period,
+            omega,
+            samples_per_symbol,
+            pulse: Pulse::new(),
+            descrambler: Scrambler::default(),
+            phase: 0.,
+            baseband: VecDeque::from(vec![(0., 0.); taps]),
+            power: 0.,
+            state: State::Idle,
+            stage: Stage::S1(0),
+            countdown: samples_per_symbol / 2.,
+            period: samples_per_symbol,
+            at_midpoint: false,
+            midpoint: (0., 0.),
+            last_symbol: (0., 0.),
+            equalizer_line: VecDeque::from(vec![(0., 0.); EQUALIZER_TAPS]),
+            taps: [(0., 0.); EQUALIZER_TAPS],
+            carrier_phase: 0.,
+            carrier_frequency: 0.,
+            last_quadrant: 0,
+            last_step: 0,
+            last_z: (0., 0.),
+            last_jump: (0., 0.),
+            out_bits: [1; 4],
+            out_counter: 0.,
+        }
+    }
+
+    /// Saída do filtro casado `frac` amostras antes da amostra mais recente,
+    /// atrasada de meio pulso.
+    fn matched_filter(&self, frac: f32) -> (f32, f32) {
+        let (mut re, mut im) = (0., 0.);
+        for (i, &(zr, zi)) in self.baseband.iter().rev().enumerate() {
+            let g = self.pulse.at((i as f32 - frac) / self.samples_per_symbol);
+            re += zr * g;
+            im += zi * g;
+        }
+        (re / self.samples_per_symbol, im / self.samples_per_symbol)
+    }
+
+    fn reset(&mut self) {
+        self.stage = Stage::S1(0);
+        self.period = self.samples_per_symbol;
+        self.equalizer_line.iter_mut().for_each(|x| *x = (0., 0.));
+        self.taps = [(0., 0.); EQUALIZER_TAPS];
+        self.carrier_phase = 0.;
+        self.carrier_frequency = 0.;
+        self.out_bits = [1; 4];
+    }
+
+    /// Indica se há portadora na linha (o sinal DCD).
+    pub fn carrier_detected(&self) -> bool {
+        matches!(self.state, State::CarrierDetected)
+    }
+
+    pub fn demodulate(&mut self, in_samples: &[f32], out_samples: &mut [u8]) {
+        debug_assert!(in_samples.len() == out_samples.len());
+
+        for (&sample, out) in in_samples.iter().zip(out_samples.iter_mut()) {
+            self.baseband.pop_front();
+            self.baseband
+                .push_back((sample * self.phase.cos(), -sample * self.phase.sin()));
+            self.phase = (self.phase + self.omega * self.sampling_period) % (2. * PI);
+
+            self.countdown -= 1.;
+            if self.countdown <= 0. {
+                let y = self.matched_filter(-self.countdown);
+                self.countdown += self.period / 2.;
+                if self.at_midpoint {
+                    self.midpoint = y;
+                } else {
+                    self.symbol(y);
+                }
+                self.at_midpoint = !self.at_midpoint;
+            }
+
+            let index = (4. * self.out_counter / self.period) as usize;
+            *out = self.out_bits[index.min(3)];
+            self.out_counter += 1.;
+        }
+    }
+
+    fn symbol(&mut self, y: (f32, f32)) {
+        let energy = y.0 * y.0 + y.1 * y.1;
+        self.power += 0.05 * (energy - self.power);
+
+        // Limiares de portadora em relação ao nível nominal do sinal no
+        // filtro casado, (DATA_LEVEL / 2)²
+        let nominal = (DATA_LEVEL / 2.).powi(2);
+        match self.state {
+            State::Idle => {
+                if self.power > 0.1 * nominal {
+                    self.state = State::CarrierDetected;
+                    self.reset();
+                }
+            }
+            State::CarrierDetected => {
+                if self.power < 0.05 * nominal {
+                    self.state = State::Idle;
+                    self.reset();
+                }
+            }
+        }
+
+        // Gardner: o erro é nulo quando o ponto médio cai na transição
+        let (mr, mi) = self.midpoint;
+        let (pr, pi) = self.last_symbol;
+        let timing_error = ((pr - y.0) * mr + (pi - y.1) * mi) / self.power.max(1e-12);
+        self.last_symbol = y;
+
+        if let State::Idle = self.state {
+            self.out_bits = [1; 4];
+            self.out_counter = 0.;
+            return;
+        }
+
+        // Até o fim da fase de 1200 bit/s os laços são mais largos, para
+        // adquirir rapidamente desvios de relógio e de frequência
+        let training = match self.stage {
+            Stage::S1(_) => true,
+            Stage::Training(n) => n < SCRAMBLED_1200_SYMBOLS,
+            Stage::Data => false,
+        };
+        let (timing_gear, carrier_gear, equalizer_step): (f32, f32, f32) = if training {
+            (3., 2., 0.05)
+        } else {
+            (1., 1., 0.01)
+        };
+
+        let timing_error = timing_error.clamp(-1., 1.);
+        self.countdown += 0.05 * timing_gear * timing_error * self.samples_per_symbol;
+        self.period += 0.002 * timing_gear.powi(2) * timing_error * self.samples_per_symbol;
+        self.period = self.period.clamp(
+            0.95 * self.samples_per_symbol,
+            1.05 * self.samples_per_symbol,
+        );
+
+        // Equalizador transversal espaçado de um símbolo. Durante o S1, cujo
+        // espectro tem só duas raias, ele apenas acompanha o nível do sinal
+        self.equalizer_line.pop_front();
+        self.equalizer_line.push_back(y);
+        if let Stage::S1(_) = self.stage {
+            let gain = (CONSTELLATION_ENERGY / self.power.max(1e-12)).sqrt();
+            self.taps = [(0., 0.); EQUALIZER_TAPS];
+            self.taps[EQUALIZER_TAPS / 2] = (gain, 0.);
+        }
+        let mut u = (0., 0.);
+        for (&tap, &x) in self.taps.iter().zip(self.equalizer_line.iter()) {
+            let p = mul(tap, x);
+            u = (u.0 + p.0, u.1 + p.1);
+        }
+
+        // Portadora: laço de segunda ordem dirigido pela decisão
+        let rotation = (self.carrier_phase.cos(), self.carrier_phase.sin());
+        let z = mul(u, conj(rotation));
+        let decision = match self.stage {
+            Stage::S1(_) => decide_4(z),
+            Stage::Training(n) if n < SCRAMBLED_1200_SYMBOLS - SWITCH_MARGIN_SYMBOLS => decide_4(z),
+            Stage::Training(_) | Stage::Data => decide_16(z),
+        };
+        let d = mul(z, conj(decision));
+        let phase_error = d.1.atan2(d.0);
+        if training {
+            // No S1 os saltos de fase alternam entre +90° e -90°, então o
+            // produto de dois saltos seguidos gira o dobro do desvio de
+            // frequência; nos quatro pontos embaralhados usa-se a quarta
+            // potência. Ambos medem o desvio sem as falsas travas do laço
+            // dirigido pela decisão, que só passa a integrar depois do S1
+            let jump = mul(z, conj(self.last_z));
+            let (f, n) = if let Stage::S1(_) = self.stage {
+                (mul(jump, self.last_jump), 2.)
+            } else {
+                let z2 = mul(jump, jump);
+                (mul(z2, z2), 4.)
+            };
+            self.carrier_frequency += 0.1 * f.1.atan2(f.0) / n;
+            self.last_jump = jump;
+        }
+        self.last_z = z;
+        if !matches!(self.stage, Stage::S1(_)) {
+            self.carrier_frequency += 0.01 * carrier_gear.powi(2) * phase_error;
+        }
+        self.carrier_phase =
+            (self.carrier_phase + self.carrier_frequency + 0.2 * carrier_gear * phase_error)
+                % (2. * PI);
+
+        // LMS normalizado, com o erro levado de volta ao referencial do
+        // equalizador
+        if !matches!(self.stage, Stage::S1(_)) {
+            let error = mul((decision.0 - z.0, decision.1 - z.1), rotation);
+            let mu = equalizer_step / (EQUALIZER_TAPS as f32 * self.power.max(1e-12));
+            for (tap, &x) in self.taps.iter_mut().zip(self.equalizer_line.iter()) {
+                let p = mul(error, conj(x));
+                *tap = (tap.0 + mu * p.0, tap.1 + mu * p.1);
+            }
+        }
+
+        // Decodificação diferencial: Q1Q2 pela mudança de quadrante, Q3Q4
+        // pela posição dentro do quadrante
+        let q = quadrant(decision);
+        let step = (q + 4 - self.last_quadrant) & 3;
+        self.last_quadrant = q;
+        let [q1, q2] = quadrant_to_dibit(step);
+        let (a, b) = rotate(decision, 4 - q);
+        let bits = [q1, q2, (b > 2.) as u8, (a > 2.) as u8];
+
+        // Os dibits 00 e 11 do S1 giram a fase de 1 e 3 quadrantes,
+        // alternadamente
+        let alternating = step + self.last_step == 4 && step != 2;
+        self.last_step = step;
+
+        self.stage = match self.stage {
+            Stage::S1(count) if alternating => Stage::S1(count + 1),
+            Stage::S1(count) if count >= S1_DETECT_SYMBOLS => {
+                self.descrambler.descramble(q1);
+                self.descrambler.descramble(q2);
+                Stage::Training(1)
+            }
+            Stage::S1(_) => Stage::S1(0),
+            Stage::Training(n) => {
+                if n < SCRAMBLED_1200_SYMBOLS {
+                    self.descrambler.descramble(q1);
+                    self.descrambler.descramble(q2);
+                } else {
+                    self.descramble(bits);
+                }
+                if n + 1 == SCRAMBLED_1200_SYMBOLS + SCRAMBLED_2400_SYMBOLS {
+                    Stage::Data
+                } else {
+                    Stage::Training(n + 1)
+                }
+            }
+            Stage::Data => {
+                self.out_bits = self.descramble(bits);
+                Stage::Data
+            }
+        };
+        self.out_counter = 0.;
+    }
+
+    fn descramble(&mut self, bits: [u8; 4]) -> [u8; 4] {
+        bits.map(|bit| self.descrambler.descramble(bit))
+    }
+}
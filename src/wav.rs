@@ -0,0 +1,343 @@
+use crate::audio::{AudioSink, AudioSource};
+use crate::g711::Law;
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Códigos de formato do cabeçalho WAV para o G.711, que o hound não trata.
+const WAVE_FORMAT_ALAW: u16 = 6;
+const WAVE_FORMAT_MULAW: u16 = 7;
+
+/// Amostras lidas de um arquivo WAV de inteiros (8 a 32 bits), de ponto
+/// flutuante ou de G.711; só o primeiro canal é usado.
+pub struct WavInput {
+    reader: Reader,
+    channels: usize,
+    sample_rate: usize,
+}
+
+enum Reader {
+    Hound(WavReader<BufReader<File>>),
+    /// Bytes do bloco de dados de um arquivo G.711
+    G711(io::Take<BufReader<File>>, Law),
+}
+
+impl WavInput {
+    pub fn open<P: AsRef<Path>>(path: P) -> hound::Result<Self> {
+        match WavReader::open(&path) {
+            Ok(reader) => {
+                let spec = reader.spec();
+                Ok(Self {
+                    reader: Reader::Hound(reader),
+                    channels: spec.channels as usize,
+                    sample_rate: spec.sample_rate as usize,
+                })
+            }
+            Err(hound::Error::Unsupported) => Self::open_g711(path),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Percorre os blocos RIFF de um arquivo com formato não suportado pelo
+    /// hound, aceitando-o se for G.711 de 8 bits.
+    fn open_g711<P: AsRef<Path>>(path: P) -> hound::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut header = [0; 12];
+        file.read_exact(&mut header)?;
+        if &header[..4] != b"RIFF" || &header[8..] != b"WAVE" {
+            return Err(hound::Error::FormatError("no RIFF/WAVE tag found"));
+        }
+
+        let mut format = None;
+        loop {
+            let mut chunk = [0; 8];
+            file.read_exact(&mut chunk)?;
+            let len = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+            match &chunk[..4] {
+                b"fmt " => {
+                    let mut fmt = vec![0; (len + len % 2) as usize];
+                    file.read_exact(&mut fmt)?;
+                    if fmt.len() < 16 {
+                        return Err(hound::Error::FormatError("invalid fmt chunk size"));
+                    }
+                    let u16_at = |i: usize| u16::from_le_bytes([fmt[i], fmt[i + 1]]);
+                    let law = match u16_at(0) {
+                        WAVE_FORMAT_MULAW => Law::ULaw,
+                        WAVE_FORMAT_ALAW => Law::ALaw,
+                        _ => return Err(hound::Error::Unsupported),
+                    };
+                    if u16_at(14) != 8 {
+                        return Err(hound::Error::Unsupported);
+                    }
+                    let channels = u16_at(2) as usize;
+                    let sample_rate = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
+                    format = Some((law, channels, sample_rate as usize));
+                }
+                b"data" => {
+                    let Some((law, channels, sample_rate)) = format else {
+                        return Err(hound::Error::FormatError("data chunk before fmt chunk"));
+                    };
+                    return Ok(Self {
+                        reader: Reader::G711(file.take(len), law),
+                        channels,
+                        sample_rate,
+                    });
+                }
+                // Blocos têm tamanho par; o byte de enchimento não é contado
+                _ => {
+                    file.seek_relative((len + len % 2) as i64)?;
+                }
+            }
+        }
+    }
+
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    /// Preenche o início de `out_samples` com as próximas amostras, entre -1
+    /// e 1, e devolve quantas foram lidas; menos que o pedido indica o fim do
+    /// arquivo.
+    pub fn read(&mut self, out_samples: &mut [f32]) -> hound::Result<usize> {
+        let wanted = out_samples.len() * self.channels;
+        let mut read = 0;
+        match &mut self.reader {
+            Reader::Hound(reader) => {
+                let spec = reader.spec();
+                match spec.sample_format {
+                    SampleFormat::Float => {
+                        for (i, sample) in reader.samples::<f32>().take(wanted).enumerate() {
+                            let sample = sample?;
+                            if i % self.channels == 0 {
+                                out_samples[i / self.channels] = sample;
+                                read += 1;
+                            }
+                        }
+                    }
+                    SampleFormat::Int => {
+                        let scale = 1. / (1u64 << (spec.bits_per_sample - 1)) as f32;
+                        for (i, sample) in reader.samples::<i32>().take(wanted).enumerate() {
+                            let sample = sample?;
+                            if i % self.channels == 0 {
+                                out_samples[i / self.channels] = sample as f32 * scale;
+                                read += 1;
+                            }
+                        }
+                    }
+                }
+            }
+            Reader::G711(reader, law) => {
+                let mut codes = Vec::with_capacity(wanted);
+                reader.take(wanted as u64).read_to_end(&mut codes)?;
+                for frame in codes.chunks_exact(self.channels) {
+                    out_samples[read] = law.decode(frame[0]);
+                    read += 1;
+                }
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// Amostras gravadas num arquivo WAV de um canal, em inteiros de 16 bits, em
+/// ponto flutuante de 32 bits ou em G.711.
+pub struct WavOutput {
+    writer: Writer,
+    sample_rate: usize,
+}
+
+enum Writer {
+    Hound(WavWriter<BufWriter<File>>, bool),
+    G711(G711Writer),
+}
+
+impl WavOutput {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: usize, float: bool) -> hound::Result<Self> {
+        let spec = if float {
+            WavSpec {
+                channels: 1,
+                sample_rate: sample_rate as u32,
+                bits_per_sample: 32,
+                sample_format: SampleFormat::Float,
+            }
+        } else {
+            WavSpec {
+                channels: 1,
+                sample_rate: sample_rate as u32,
+                bits_per_sample: 16,
+                sample_format: SampleFormat::Int,
+            }
+        };
+        let writer = WavWriter::create(path, spec)?;
+        Ok(Self {
+            writer: Writer::Hound(writer, float),
+            sample_rate,
+        })
+    }
+
+    /// Cria um arquivo G.711 de 8 bits pela lei dada.
+    pub fn create_g711<P: AsRef<Path>>(
+        path: P,
+        sample_rate: usize,
+        law: Law,
+    ) -> hound::Result<Self> {
+        let writer = G711Writer::create(path, sample_rate, law)?;
+        Ok(Self {
+            writer: Writer::G711(writer),
+            sample_rate,
+        })
+    }
+
+    /// Grava as amostras, limitando-as ao intervalo de -1 a 1.
+    pub fn write(&mut self, samples: &[f32]) -> hound::Result<()> {
+        match &mut self.writer {
+            Writer::Hound(writer, float) => {
+                for &sample in samples {
+                    let sample = sample.clamp(-1., 1.);
+                    if *float {
+                        writer.write_sample(sample)?;
+                    } else {
+                        // A mesma escala da leitura, 2^15, saturando em +1
+                        let sample = (sample * 32768.).round().min(i16::MAX as f32);
+                        writer.write_sample(sample as i16)?;
+                    }
+                }
+            }
+            Writer::G711(writer) => writer.write(samples)?,
+        }
+        Ok(())
+    }
+
+    /// Atualiza o cabeçalho, de modo que o arquivo fique legível mesmo que o
+    /// programa seja interrompido.
+    pub fn flush(&mut self) -> hound::Result<()> {
+        match &mut self.writer {
+            Writer::Hound(writer, _) => writer.flush(),
+            Writer::G711(writer) => Ok(writer.update_header()?),
+        }
+    }
+
+    pub fn finalize(self) -> hound::Result<()> {
+        match self.writer {
+            Writer::Hound(writer, _) => writer.finalize(),
+            Writer::G711(writer) => Ok(writer.finalize()?),
+        }
+    }
+}
+
+/// Gravação de um arquivo WAV G.711 de um canal, com os blocos `fmt `,
+/// `fact` (obrigatório fora do PCM) e `data`.
+struct G711Writer {
+    file: BufWriter<File>,
+    law: Law,
+    samples: u32,
+}
+
+impl G711Writer {
+    /// Posições no cabeçalho dos campos atualizados ao longo da gravação.
+    const RIFF_SIZE: u64 = 4;
+    const FACT_SAMPLES: u64 = 46;
+    const DATA_SIZE: u64 = 54;
+    const HEADER_SIZE: u32 = 58;
+
+    fn create<P: AsRef<Path>>(path: P, sample_rate: usize, law: Law) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let tag = match law {
+            Law::ULaw => WAVE_FORMAT_MULAW,
+            Law::ALaw => WAVE_FORMAT_ALAW,
+        };
+        file.write_all(b"RIFF")?;
+        file.write_all(&(Self::HEADER_SIZE - 8).to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&18u32.to_le_bytes())?;
+        file.write_all(&tag.to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&(sample_rate as u32).to_le_bytes())?;
+        // Bytes por segundo, bytes por quadro, bits por amostra e tamanho
+        // da extensão do formato
+        file.write_all(&(sample_rate as u32).to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&8u16.to_le_bytes())?;
+        file.write_all(&0u16.to_le_bytes())?;
+        file.write_all(b"fact")?;
+        file.write_all(&4u32.to_le_bytes())?;
+        file.write_all(&0u32.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            file,
+            law,
+            samples: 0,
+        })
+    }
+
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        let codes: Vec<u8> = samples.iter().map(|&s| self.law.encode(s)).collect();
+        self.file.write_all(&codes)?;
+        self.samples += codes.len() as u32;
+        Ok(())
+    }
+
+    fn update_header(&mut self) -> io::Result<()> {
+        let riff_size = Self::HEADER_SIZE - 8 + self.samples + self.samples % 2;
+        self.file.flush()?;
+        let file = self.file.get_mut();
+        for (position, value) in [
+            (Self::RIFF_SIZE, riff_size),
+            (Self::FACT_SAMPLES, self.samples),
+            (Self::DATA_SIZE, self.samples),
+        ] {
+            file.seek(SeekFrom::Start(position))?;
+            file.write_all(&value.to_le_bytes())?;
+        }
+        file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    fn finalize(mut self) -> io::Result<()> {
+        // O bloco de dados é completado até um tamanho par
+        if self.samples % 2 == 1 {
+            self.file.write_all(&[0])?;
+        }
+        self.update_header()?;
+        self.file.flush()
+    }
+}
+
+/// Erros de E/S do hound voltam a ser `io::Error`; os de formato viram
+/// `InvalidData`.
+fn io_error(err: hound::Error) -> io::Error {
+    match err {
+        hound::Error::IoError(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+impl AudioSource for WavInput {
+    fn sample_rate(&self) -> usize {
+        self.sample_rate()
+    }
+
+    fn read(&mut self, samples: &mut [f32]) -> io::Result<usize> {
+        self.read(samples).map_err(io_error)
+    }
+}
+
+impl AudioSink for WavOutput {
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        self.write(samples).map_err(io_error)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush().map_err(io_error)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.finalize().map_err(io_error)
+    }
+}